@@ -1,14 +1,38 @@
-use clap::{builder::ValueRange, ArgAction, Parser};
+use clap::{
+    builder::{RangedU64ValueParser, ValueRange},
+    error::ErrorKind,
+    ArgAction, CommandFactory, Parser,
+};
+
+use crate::clip::Extent;
+use crate::file_operations::{OutputFormat, STDIO};
+use crate::geo_operations::{
+    AreaThreshold, BboxMode, SimplifyAlgorithm, ToleranceUnit, WindingOrder, MAX_DECIMALS,
+};
+use crate::pipeline::Step;
+use crate::projection::Crs;
+use std::path::PathBuf;
+
+/// The output directory when `--output` is not given.
+pub const DEFAULT_OUTPUT: &str = "./output/";
 
 #[derive(Parser, Debug)]
 #[command(author = "Fafa",name = "mini-geojson",  version, about, long_about = None)]
 pub struct Args {
-    /// Sets the path to the input GeoJSON file.
-    #[clap(short, long, required = true)]
-    pub input: String,
+    /// Sets the paths to the input GeoJSON files, directories or glob patterns.
+    /// example: -i a.geojson b.geojson data/ 'exports/*.json'
+    /// Note: TopoJSON files are converted to a GeoJSON FeatureCollection.
+    /// With several files, the output is a directory.
+    /// Use '-' to read from stdin.
+    #[clap(short, long, required = true, num_args = 1..)]
+    pub input: Vec<String>,
+
+    /// Also process the files in the subdirectories of the input directories.
+    #[clap(long, action = ArgAction::SetTrue)]
+    pub recursive: bool,
 
     /// Sets the path to the output GeoJSON file.
-    /// Note: '.json' extension will be replaced with '.geojson'.
+    /// Note: '.json' extension will be replaced with '.geojson' ('.topojson' with --format topojson).
     ///
     /// Examples:
     /// - '/your-output-dir/your-output-file.json'
@@ -16,13 +40,44 @@ pub struct Args {
     ///
     /// - './output/'
     ///   will use the input filename with '.geojson' extension.
-    #[clap(short, long, default_value = "./output/")]
+    ///
+    /// - '-'
+    ///   writes to stdout, the size reduction is then reported on stderr.
+    ///   This is the default when reading from stdin.
+    #[clap(short, long, default_value = DEFAULT_OUTPUT)]
     pub output: String,
 
     /// Sets the number of decimals to keep.
     #[clap(short, long)]
     pub decimal: Option<usize>,
 
+    /// Rounds the coordinates to this ground resolution in meters, instead of -d.
+    /// The longitude decimals follow the latitude of each position;
+    /// projected coordinates and elevations are rounded in meters.
+    /// example: --precision-meters 1 keeps 5 decimals of latitude.
+    #[clap(long, conflicts_with = "significant_digits")]
+    pub precision_meters: Option<f64>,
+
+    /// Keeps this number of significant digits in each coordinate, instead of -d.
+    /// Suited to projected coordinates, whose integer part already spans several digits.
+    /// example: --significant-digits 7 writes 6862036.8 as 6862037.
+    #[clap(long)]
+    pub significant_digits: Option<u32>,
+
+    /// Sets the number of decimals of the elevations (Z, the third ordinate), instead of -d.
+    /// example: --z-decimal 2 keeps the elevations to the centimetre.
+    #[clap(long, value_parser = RangedU64ValueParser::<usize>::new().range(0..=MAX_DECIMALS as u64))]
+    pub z_decimal: Option<usize>,
+
+    /// Sets the number of decimals of the measures (M, the fourth ordinate), instead of -d.
+    #[clap(long, value_parser = RangedU64ValueParser::<usize>::new().range(0..=MAX_DECIMALS as u64))]
+    pub m_decimal: Option<usize>,
+
+    /// Drops the elevations and measures, keeping 2D positions.
+    /// Note: 3D bboxes are reduced to their horizontal extent.
+    #[clap(long, action = ArgAction::SetTrue)]
+    pub force_2d: bool,
+
     /// Overwrites the output file if it already exists.
     #[clap(short = 'O', long, action = ArgAction::SetTrue)]
     pub overwrite: bool,
@@ -47,4 +102,241 @@ pub struct Args {
     /// Note: -r or -k can be used together.
     #[clap(short = 'k', long, num_args = ValueRange::new(0..), value_delimiter = ',')]
     pub properties_to_keep: Option<Vec<String>>,
+
+    /// Simplify LineStrings and polygon rings with the given tolerance.
+    /// With douglas-peucker, vertices closer than the tolerance to the simplified line are removed.
+    /// With visvalingam, vertices with an effective area below the tolerance are removed.
+    /// Note: rings are kept closed with at least 4 positions.
+    #[clap(short, long)]
+    pub simplify: Option<f64>,
+
+    /// Sets the unit of the simplification tolerance.
+    /// Note: with visvalingam, the tolerance is an area in square degrees or square meters.
+    #[clap(long, value_enum, default_value_t = ToleranceUnit::Degrees)]
+    pub simplify_unit: ToleranceUnit,
+
+    /// Sets the simplification algorithm.
+    #[clap(long, value_enum, default_value_t = SimplifyAlgorithm::DouglasPeucker)]
+    pub simplify_algorithm: SimplifyAlgorithm,
+
+    /// Keep this percentage of the vertices of each line and ring (visvalingam only).
+    /// example: --simplify-keep 10 keeps 10% of the vertices.
+    /// Note: requires --simplify-algorithm visvalingam.
+    #[clap(long, conflicts_with = "simplify")]
+    pub simplify_keep: Option<f64>,
+
+    /// Simplify the borders shared by neighbouring features only once,
+    /// so that adjacent polygons keep touching without gaps or overlaps.
+    #[clap(long, action = ArgAction::SetTrue)]
+    pub preserve_topology: bool,
+
+    /// Repair unclosed rings, duplicate vertices, degenerate rings, empty parts,
+    /// invalid or out-of-range coordinates and swapped lat/lon.
+    /// A summary of the changes is printed for each feature.
+    #[clap(long, action = ArgAction::SetTrue)]
+    pub fix: bool,
+
+    /// Keep only what is inside this bbox, in the output CRS: 'minx,miny,maxx,maxy'.
+    /// The features left empty are removed.
+    /// example: --clip-bbox -5.2,47.2,-1,48.9
+    #[clap(long, allow_hyphen_values = true)]
+    pub clip_bbox: Option<Extent>,
+
+    /// Keep only what is inside the polygons of this GeoJSON file, in the output CRS.
+    /// The features left empty are removed.
+    /// Note: the polygons of the mask must not overlap, the borders they share are dissolved.
+    /// example: --clip-mask bretagne.geojson
+    #[clap(long, conflicts_with = "clip_bbox")]
+    pub clip_mask: Option<PathBuf>,
+
+    /// Keep only the features entirely inside the polygons of this GeoJSON file,
+    /// in the output CRS, without changing their geometry.
+    /// Note: like --intersects and --bbox-filter, tests the input geometry, before clipping and simplification.
+    /// example: --within bretagne.geojson
+    #[clap(long)]
+    pub within: Option<PathBuf>,
+
+    /// Keep only the features with a part inside the polygons of this GeoJSON file,
+    /// in the output CRS, without changing their geometry.
+    /// Note: lines and polygons only touching the border of the mask are discarded.
+    /// example: --intersects bretagne.geojson
+    #[clap(long)]
+    pub intersects: Option<PathBuf>,
+
+    /// Keep only the features whose bbox overlaps this one, in the output CRS:
+    /// 'minx,miny,maxx,maxy'. The geometries are not changed.
+    /// example: --bbox-filter -5.2,47.2,-1,48.9
+    #[clap(long, allow_hyphen_values = true)]
+    pub bbox_filter: Option<Extent>,
+
+    /// Remove the parts of MultiPolygons smaller than this area, in m² or as a percentage
+    /// of the feature area. The largest part of each feature is always kept.
+    /// example: --min-part-area 10000 or --min-part-area 1%
+    #[clap(long)]
+    pub min_part_area: Option<AreaThreshold>,
+
+    /// Remove the holes smaller than this area, in m² or as a percentage of the feature area.
+    /// example: --min-hole-area 5000 or --min-hole-area 0.5%
+    #[clap(long)]
+    pub min_hole_area: Option<AreaThreshold>,
+
+    /// Remove the features whose polygons cover less than this area, in m².
+    /// Features without polygons are kept.
+    #[clap(long)]
+    pub min_feature_area: Option<f64>,
+
+    /// Cut the LineStrings and Polygons crossing the antimeridian into
+    /// MultiLineStrings and MultiPolygons, as RFC 7946 recommends.
+    /// Note: only WGS84 output has an antimeridian to cut at.
+    #[clap(long, action = ArgAction::SetTrue)]
+    pub split_antimeridian: bool,
+
+    /// Remove the consecutive duplicate vertices and the collinear midpoints left by rounding,
+    /// keeping rings closed and valid. An optional angle in degrees also removes
+    /// the vertices where the line turns by at most this angle.
+    /// example: --clean or --clean 0.5
+    #[clap(long, num_args = 0..=1, default_missing_value = "0")]
+    pub clean: Option<f64>,
+
+    /// Orient the polygon rings: counter-clockwise exterior rings and clockwise holes
+    /// ('rfc7946', the default value), or the opposite ('clockwise', the legacy d3 convention).
+    /// example: --rewind or --rewind clockwise
+    #[clap(long, value_enum, num_args = 0..=1, default_missing_value = "rfc7946")]
+    pub rewind: Option<WindingOrder>,
+
+    /// What to do with the 'bbox' members: 'keep' them, rounded like the coordinates,
+    /// 'compute' them on each feature and on the collection, or 'strip' them to save bytes.
+    /// Note: streaming only supports 'keep'.
+    #[clap(long, value_enum, default_value_t = BboxMode::Keep)]
+    pub bbox: BboxMode,
+
+    /// Sets the CRS of the input: '4326' (WGS84), '3857' (Web Mercator) or '2154' (Lambert-93).
+    /// Note: by default, the CRS named by the legacy 'crs' member, WGS84 without one.
+    /// An input whose 'crs' member names another CRS needs this option.
+    #[clap(long, value_enum)]
+    pub from_crs: Option<Crs>,
+
+    /// Reprojects the coordinates to this CRS: '4326' (WGS84), '3857' (Web Mercator) or '2154' (Lambert-93).
+    /// Note: the 'crs' member is removed for WGS84, as RFC 7946 requires, and names the CRS otherwise.
+    /// Streaming does not reproject.
+    #[clap(long, value_enum, default_value_t = Crs::Wgs84)]
+    pub to_crs: Crs,
+
+    /// Sets the order of the processing steps, separated by commas.
+    /// Steps whose option is not set are skipped.
+    /// example: --step remove-properties,remove-nulls,truncate
+    /// 'reproject,fix,clip,min-area,simplify,antimeridian,truncate,clean,fix,rewind,bbox,remove-nulls,remove-properties,keep-properties' by default.
+    #[clap(long, value_enum, value_delimiter = ',', num_args = 1..)]
+    pub step: Option<Vec<Step>>,
+
+    /// Sets the output format.
+    /// Note: by default, TopoJSON is written when the output ends with '.topojson'.
+    #[clap(short, long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Sets the number of steps of the TopoJSON quantization grid.
+    /// Note: 0 disables quantization and delta encoding, a grid has at least 2 steps otherwise.
+    #[clap(short, long, default_value_t = 10_000)]
+    pub quantization: u32,
+
+    /// Read, process and write the features one at a time,
+    /// so that files larger than memory can be processed.
+    /// Note: only for WGS84 FeatureCollections, not available with --preserve-topology and TopoJSON output.
+    /// The collection bbox is removed when features may be clipped or removed
+    /// (--clip-*, --min-feature-area, --within, --intersects, --bbox-filter).
+    #[clap(long, action = ArgAction::SetTrue)]
+    pub stream: bool,
+
+    /// Sets the number of threads used to process the features.
+    /// Note: 0 uses one thread per CPU core. The features keep their order.
+    #[clap(short, long, default_value_t = 1)]
+    pub jobs: usize,
+
+    /// Check the inputs against RFC 7946 instead of minifying them.
+    /// Each problem is reported with its JSON pointer and its severity,
+    /// and the exit code is non-zero when an input has errors.
+    #[clap(long, action = ArgAction::SetTrue)]
+    pub validate: bool,
+}
+
+impl Args {
+    /// Whether the output is written to stdout, with `-o -` or by default when reading from stdin.
+    pub fn writes_to_stdout(&self) -> bool {
+        self.output == STDIO || (self.output == DEFAULT_OUTPUT && self.input == [STDIO])
+    }
+
+    /// Check the usage errors that the clap attributes cannot express.
+    pub fn check(&self) -> Result<(), clap::Error> {
+        if self.simplify_keep.is_some() && self.simplify_algorithm != SimplifyAlgorithm::Visvalingam
+        {
+            return Err(Args::command().error(
+                ErrorKind::ArgumentConflict,
+                "--simplify-keep requires --simplify-algorithm visvalingam",
+            ));
+        }
+        if self.quantization == 1 {
+            return Err(Args::command().error(
+                ErrorKind::ValueValidation,
+                "--quantization needs at least 2 steps, or 0 to disable quantization",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simplify_keep_requires_visvalingam() {
+        let args = |algorithm: &str| {
+            Args::parse_from([
+                "mini-geojson",
+                "-i",
+                "in.geojson",
+                "--simplify-keep",
+                "10",
+                "--simplify-algorithm",
+                algorithm,
+            ])
+        };
+        assert_eq!(
+            args("douglas-peucker").check().unwrap_err().kind(),
+            ErrorKind::ArgumentConflict
+        );
+        assert!(args("visvalingam").check().is_ok());
+    }
+
+    #[test]
+    fn test_quantization_has_at_least_two_steps() {
+        let args = |quantization: &str| {
+            Args::parse_from(["mini-geojson", "-i", "in.geojson", "-q", quantization])
+        };
+        assert_eq!(
+            args("1").check().unwrap_err().kind(),
+            ErrorKind::ValueValidation
+        );
+        assert!(args("0").check().is_ok());
+        assert!(args("2").check().is_ok());
+    }
+
+    #[test]
+    fn test_stdin_is_written_to_stdout_by_default() {
+        let args = |options: &[&str]| Args::parse_from(["mini-geojson"].iter().chain(options));
+        assert!(args(&["-i", "-"]).writes_to_stdout());
+        assert!(args(&["-i", "in.geojson", "-o", "-"]).writes_to_stdout());
+        assert!(!args(&["-i", "in.geojson"]).writes_to_stdout());
+        assert!(!args(&["-i", "-", "-o", "out.geojson"]).writes_to_stdout());
+    }
+
+    #[test]
+    fn test_z_and_m_decimals_are_bounded() {
+        let args = |option: &str, decimal: &str| {
+            Args::try_parse_from(["mini-geojson", "-i", "in.geojson", option, decimal])
+        };
+        assert_eq!(args("--z-decimal", "15").unwrap().z_decimal, Some(15));
+        assert!(args("--z-decimal", "25").is_err());
+        assert!(args("--m-decimal", "16").is_err());
+    }
 }
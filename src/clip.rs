@@ -0,0 +1,1074 @@
+use crate::file_operations::MyError;
+use crate::geo_operations::{compute_geometry_bbox, positions, positions_to_value};
+use log::debug;
+use sonic_rs::{Array, JsonContainerTrait, JsonValueMutTrait, JsonValueTrait, Value};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+
+type Point = (f64, f64);
+
+/// Position key made of the bits of x and y, so positions can be hashed and compared exactly.
+type PointKey = (u64, u64);
+
+/// Tolerance on the parameters of an intersection along its two edges.
+const EPSILON: f64 = 1e-12;
+/// Distance below which a point lies on a boundary, in coordinate units.
+const BOUNDARY_DISTANCE: f64 = 1e-9;
+
+fn key(point: Point) -> PointKey {
+    // Adding 0.0 turns -0.0 into 0.0, which has other bits
+    ((point.0 + 0.0).to_bits(), (point.1 + 0.0).to_bits())
+}
+
+fn cross(a: Point, b: Point) -> f64 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn midpoint(a: Point, b: Point) -> Point {
+    ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0)
+}
+
+fn signed_area(ring: &[Point]) -> f64 {
+    ring_edges(ring).map(|(a, b)| cross(a, b)).sum::<f64>() / 2.0
+}
+
+/// Edges of an open ring, the last one going back to the first position.
+fn ring_edges(ring: &[Point]) -> impl Iterator<Item = (Point, Point)> + '_ {
+    (0..ring.len()).map(move |i| (ring[i], ring[(i + 1) % ring.len()]))
+}
+
+fn segment_distance(p: Point, a: Point, b: Point) -> f64 {
+    let ab = sub(b, a);
+    let length = ab.0 * ab.0 + ab.1 * ab.1;
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        (((p.0 - a.0) * ab.0 + (p.1 - a.1) * ab.1) / length).clamp(0.0, 1.0)
+    };
+    (a.0 + t * ab.0 - p.0).hypot(a.1 + t * ab.1 - p.1)
+}
+
+fn bbox_of<'a>(points: impl IntoIterator<Item = &'a Point>) -> [f64; 4] {
+    points.into_iter().fold(
+        [
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ],
+        |bbox, p| {
+            [
+                bbox[0].min(p.0),
+                bbox[1].min(p.1),
+                bbox[2].max(p.0),
+                bbox[3].max(p.1),
+            ]
+        },
+    )
+}
+
+fn bboxes_intersect(a: &[f64; 4], b: &[f64; 4]) -> bool {
+    a[0] <= b[2] && b[0] <= a[2] && a[1] <= b[3] && b[1] <= a[3]
+}
+
+/// Edges of a set of rings, indexed by horizontal bands
+/// to find the edges crossing a segment or a ray without visiting all of them.
+#[derive(Debug)]
+struct EdgeIndex {
+    edges: Vec<(Point, Point)>,
+    /// Index of the first edge of each ring.
+    ring_starts: Vec<usize>,
+    min_y: f64,
+    band_height: f64,
+    bands: Vec<Vec<usize>>,
+}
+
+impl EdgeIndex {
+    fn new(rings: &[Vec<Point>]) -> Self {
+        EdgeIndex::with_edges(rings, |_| true)
+    }
+
+    /// Index of the edges of the rings for which `keep` is true.
+    fn with_edges(rings: &[Vec<Point>], mut keep: impl FnMut(&(Point, Point)) -> bool) -> Self {
+        let mut ring_starts = Vec::with_capacity(rings.len());
+        let mut edges = Vec::new();
+        for ring in rings {
+            ring_starts.push(edges.len());
+            edges.extend(ring_edges(ring).filter(|edge| keep(edge)));
+        }
+        let (min_y, max_y) = edges
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (a, b)| {
+                (min.min(a.1).min(b.1), max.max(a.1).max(b.1))
+            });
+        let count = ((edges.len() as f64).sqrt().ceil() as usize).max(1);
+        let mut index = EdgeIndex {
+            edges,
+            ring_starts,
+            min_y,
+            band_height: ((max_y - min_y) / count as f64).max(f64::MIN_POSITIVE),
+            bands: vec![Vec::new(); count],
+        };
+        for id in 0..index.edges.len() {
+            let (a, b) = index.edges[id];
+            for band in index.band(a.1.min(b.1))..=index.band(a.1.max(b.1)) {
+                index.bands[band].push(id);
+            }
+        }
+        index
+    }
+
+    fn band(&self, y: f64) -> usize {
+        let band = ((y - self.min_y) / self.band_height).floor().max(0.0) as usize;
+        band.min(self.bands.len() - 1)
+    }
+
+    /// Edges of the ring at `ring`, as a range of edge ids.
+    fn ring_range(&self, ring: usize) -> std::ops::Range<usize> {
+        let end = self
+            .ring_starts
+            .get(ring + 1)
+            .copied()
+            .unwrap_or(self.edges.len());
+        self.ring_starts[ring]..end
+    }
+
+    /// Ids of the edges whose bands overlap the latitudes from `y0` to `y1`, each once.
+    fn candidates(&self, y0: f64, y1: f64) -> Vec<usize> {
+        let mut ids: Vec<usize> = (self.band(y0)..=self.band(y1))
+            .flat_map(|band| self.bands[band].iter().copied())
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// Even-odd rule: whether a ray from the point crosses the edges an odd number of times.
+    fn contains(&self, p: Point) -> bool {
+        let mut inside = false;
+        for &id in &self.bands[self.band(p.1)] {
+            let (a, b) = self.edges[id];
+            if (a.1 > p.1) != (b.1 > p.1) && p.0 < a.0 + (p.1 - a.1) / (b.1 - a.1) * (b.0 - a.0) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// An edge the point lies on, if any.
+    fn edge_at(&self, p: Point) -> Option<(Point, Point)> {
+        self.candidates(p.1 - BOUNDARY_DISTANCE, p.1 + BOUNDARY_DISTANCE)
+            .into_iter()
+            .map(|id| self.edges[id])
+            .find(|&(a, b)| segment_distance(p, a, b) <= BOUNDARY_DISTANCE)
+    }
+}
+
+/// A `minx,miny,maxx,maxy` extent, as given on the command line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Extent(pub [f64; 4]);
+
+impl FromStr for Extent {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid bbox, expected minx,miny,maxx,maxy: {:?}", value);
+        let numbers: Vec<f64> = value
+            .split(',')
+            .map(|number| number.trim().parse::<f64>().ok().filter(|n| n.is_finite()))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        match numbers[..] {
+            [minx, miny, maxx, maxy] if minx < maxx && miny < maxy => {
+                Ok(Extent([minx, miny, maxx, maxy]))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// An area geometries are clipped to: a bbox, or the polygons of a GeoJSON file.
+/// The polygons must not overlap each other, the borders they share are dissolved.
+#[derive(Debug)]
+pub struct Mask {
+    ring_bboxes: Vec<[f64; 4]>,
+    bbox: [f64; 4],
+    index: EdgeIndex,
+}
+
+impl Mask {
+    /// Mask of a `[minx, miny, maxx, maxy]` bbox.
+    pub fn from_bbox(bbox: [f64; 4]) -> Self {
+        let [west, south, east, north] = bbox;
+        Mask::new(vec![vec![
+            (west, south),
+            (east, south),
+            (east, north),
+            (west, north),
+        ]])
+    }
+
+    /// Mask of the Polygons and MultiPolygons of a GeoJSON object.
+    pub fn from_geojson(geojson: &Value) -> Result<Self, MyError> {
+        let mut rings = Vec::new();
+        collect_rings(geojson, &mut rings);
+        if rings.is_empty() {
+            return Err(MyError::InvalidGeoJson(
+                "the clip mask has no polygon".to_string(),
+            ));
+        }
+        Ok(Mask::new(rings))
+    }
+
+    /// Mask of open rings, exteriors counter-clockwise and holes clockwise.
+    fn new(rings: Vec<Vec<Point>>) -> Self {
+        let ring_bboxes: Vec<[f64; 4]> = rings.iter().map(bbox_of).collect();
+        // An edge also present in the other direction is a border between two polygons
+        let directed: HashSet<(PointKey, PointKey)> = rings
+            .iter()
+            .flat_map(|ring| ring_edges(ring))
+            .map(|(a, b)| (key(a), key(b)))
+            .collect();
+        Mask {
+            bbox: bbox_of(rings.iter().flatten()),
+            index: EdgeIndex::with_edges(&rings, |&(a, b)| !directed.contains(&(key(b), key(a)))),
+            ring_bboxes,
+        }
+    }
+
+    pub fn bbox(&self) -> [f64; 4] {
+        self.bbox
+    }
+
+    /// Whether the point is inside the mask or on its boundary.
+    fn covers(&self, p: Point) -> bool {
+        let [west, south, east, north] = self.bbox;
+        let margin = BOUNDARY_DISTANCE;
+        if p.0 < west - margin
+            || p.0 > east + margin
+            || p.1 < south - margin
+            || p.1 > north + margin
+        {
+            return false;
+        }
+        self.index.contains(p) || self.index.edge_at(p).is_some()
+    }
+}
+
+/// Points of a ring without its closing position, oriented counter-clockwise or clockwise.
+fn ring_points(ring: &Value, counter_clockwise: bool) -> Option<Vec<Point>> {
+    let mut points: Vec<Point> = positions(ring)?.iter().map(|p| (p[0], p[1])).collect();
+    if points.len() > 1 && key(points[0]) == key(points[points.len() - 1]) {
+        points.pop();
+    }
+    if points.len() < 3 {
+        return None;
+    }
+    if (signed_area(&points) > 0.0) != counter_clockwise {
+        points.reverse();
+    }
+    Some(points)
+}
+
+fn polygon_rings(polygon: &Value, rings: &mut Vec<Vec<Point>>) {
+    let Some(polygon) = polygon.as_array() else {
+        return;
+    };
+    for (index, ring) in polygon.iter().enumerate() {
+        if let Some(points) = ring_points(ring, index == 0) {
+            rings.push(points);
+        }
+    }
+}
+
+fn collect_rings(value: &Value, rings: &mut Vec<Vec<Point>>) {
+    let members = |name: &str| {
+        value
+            .get(name)
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+    };
+    match value.get("type").and_then(|t| t.as_str()) {
+        Some("FeatureCollection") => members("features").for_each(|f| collect_rings(f, rings)),
+        Some("GeometryCollection") => members("geometries").for_each(|g| collect_rings(g, rings)),
+        Some("Feature") => {
+            if let Some(geometry) = value.get("geometry") {
+                collect_rings(geometry, rings);
+            }
+        }
+        Some("Polygon") => {
+            if let Some(coordinates) = value.get("coordinates") {
+                polygon_rings(coordinates, rings);
+            }
+        }
+        Some("MultiPolygon") => members("coordinates").for_each(|p| polygon_rings(p, rings)),
+        _ => {}
+    }
+}
+
+/// Parameters along `a`-`b` and `c`-`d` where the two segments cross, with the crossing point.
+/// Collinear overlapping segments give the ends of their overlap.
+fn intersections(a: Point, b: Point, c: Point, d: Point) -> Vec<(f64, f64, Point)> {
+    let (r, s, ac) = (sub(b, a), sub(d, c), sub(c, a));
+    let denominator = cross(r, s);
+    if denominator == 0.0 {
+        if cross(ac, r) != 0.0 {
+            return Vec::new();
+        }
+        let along = |p: Point, start: Point, direction: Point| {
+            let length = direction.0 * direction.0 + direction.1 * direction.1;
+            ((p.0 - start.0) * direction.0 + (p.1 - start.1) * direction.1) / length
+        };
+        let mut overlap = Vec::new();
+        for p in [c, d] {
+            let t = along(p, a, r);
+            if (0.0..=1.0).contains(&t) {
+                overlap.push((t, along(p, c, s), p));
+            }
+        }
+        for p in [a, b] {
+            let u = along(p, c, s);
+            if (0.0..=1.0).contains(&u) {
+                overlap.push((along(p, a, r), u, p));
+            }
+        }
+        return overlap;
+    }
+    let t = cross(ac, s) / denominator;
+    let u = cross(ac, r) / denominator;
+    if !(-EPSILON..=1.0 + EPSILON).contains(&t) || !(-EPSILON..=1.0 + EPSILON).contains(&u) {
+        return Vec::new();
+    }
+    // Snap to the existing positions, so both edges are cut at the very same point
+    let point = if t <= EPSILON {
+        a
+    } else if t >= 1.0 - EPSILON {
+        b
+    } else if u <= EPSILON {
+        c
+    } else if u >= 1.0 - EPSILON {
+        d
+    } else {
+        (a.0 + t * r.0, a.1 + t * r.1)
+    };
+    vec![(t, u, point)]
+}
+
+/// Pieces of the edge from `a` to `b`, cut at the given points.
+fn cut_edge(
+    a: Point,
+    b: Point,
+    cuts: Option<&mut Vec<(f64, Point)>>,
+    pieces: &mut Vec<(Point, Point)>,
+) {
+    let mut start = a;
+    if let Some(cuts) = cuts {
+        cuts.sort_by(|x, y| x.0.total_cmp(&y.0));
+        for &(_, point) in cuts.iter() {
+            if key(point) != key(start) && key(point) != key(b) {
+                pieces.push((start, point));
+                start = point;
+            }
+        }
+    }
+    if key(start) != key(b) {
+        pieces.push((start, b));
+    }
+}
+
+/// Result of clipping polygons.
+enum ClippedPolygons {
+    /// The polygons are inside the mask and are left as they are.
+    Inside,
+    /// Polygons of the intersection, with closed rings.
+    Parts(Vec<Vec<Vec<Point>>>),
+}
+
+/// Intersection of polygons with the mask, given all their open rings:
+/// exteriors counter-clockwise and holes clockwise.
+///
+/// The edges of both sides are cut where they cross, then the pieces of each side
+/// inside the other one are kept, and chained back into rings.
+/// Boundaries shared by the polygons and the mask are kept once, when both have
+/// their interior on the same side.
+fn clip_rings(rings: &[Vec<Point>], mask: &Mask) -> ClippedPolygons {
+    let subject = EdgeIndex::new(rings);
+    let subject_bbox = bbox_of(rings.iter().flatten());
+    if !bboxes_intersect(&subject_bbox, &mask.bbox) {
+        return ClippedPolygons::Parts(Vec::new());
+    }
+
+    let mut subject_cuts: Vec<Vec<(f64, Point)>> = vec![Vec::new(); subject.edges.len()];
+    let mut mask_cuts: HashMap<usize, Vec<(f64, Point)>> = HashMap::new();
+    let mut nodes: HashSet<PointKey> = HashSet::new();
+    for (id, &(a, b)) in subject.edges.iter().enumerate() {
+        for mask_id in mask.index.candidates(a.1.min(b.1), a.1.max(b.1)) {
+            let (c, d) = mask.index.edges[mask_id];
+            if a.0.max(b.0) < c.0.min(d.0) || d.0.max(c.0) < a.0.min(b.0) {
+                continue;
+            }
+            for (t, u, point) in intersections(a, b, c, d) {
+                nodes.insert(key(point));
+                subject_cuts[id].push((t, point));
+                mask_cuts.entry(mask_id).or_default().push((u, point));
+            }
+        }
+    }
+
+    let mut kept = Vec::new();
+    let mut subject_pieces = 0;
+    for ring in 0..rings.len() {
+        let mut pieces = Vec::new();
+        for id in subject.ring_range(ring) {
+            let (a, b) = subject.edges[id];
+            cut_edge(a, b, Some(&mut subject_cuts[id]), &mut pieces);
+        }
+        subject_pieces += pieces.len();
+        // The connected pieces between two nodes are all on the same side of the mask
+        let mut inside = false;
+        for (index, &(a, b)) in pieces.iter().enumerate() {
+            if index == 0 || nodes.contains(&key(a)) || key(pieces[index - 1].1) != key(a) {
+                let middle = midpoint(a, b);
+                inside = match mask.index.edge_at(middle) {
+                    Some((c, d)) => {
+                        let (r, s) = (sub(b, a), sub(d, c));
+                        r.0 * s.0 + r.1 * s.1 > 0.0
+                    }
+                    None => mask.index.contains(middle),
+                };
+            }
+            if inside {
+                kept.push((a, b));
+            }
+        }
+    }
+    let subject_kept = kept.len();
+
+    for (ring, ring_bbox) in mask.ring_bboxes.iter().enumerate() {
+        if !bboxes_intersect(ring_bbox, &subject_bbox) {
+            continue;
+        }
+        let mut pieces = Vec::new();
+        for id in mask.index.ring_range(ring) {
+            let (c, d) = mask.index.edges[id];
+            cut_edge(c, d, mask_cuts.get_mut(&id), &mut pieces);
+        }
+        let mut inside = false;
+        for (index, &(c, d)) in pieces.iter().enumerate() {
+            if index == 0 || nodes.contains(&key(c)) || key(pieces[index - 1].1) != key(c) {
+                let middle = midpoint(c, d);
+                inside = subject.edge_at(middle).is_none() && subject.contains(middle);
+            }
+            if inside {
+                kept.push((c, d));
+            }
+        }
+    }
+
+    if nodes.is_empty() && subject_kept == subject_pieces && kept.len() == subject_kept {
+        return ClippedPolygons::Inside;
+    }
+    ClippedPolygons::Parts(assemble_polygons(kept))
+}
+
+/// Chain directed edges into closed rings, then group the holes with their exterior.
+fn assemble_polygons(edges: Vec<(Point, Point)>) -> Vec<Vec<Vec<Point>>> {
+    let mut outgoing: HashMap<PointKey, Vec<usize>> = HashMap::new();
+    for (id, (a, _)) in edges.iter().enumerate() {
+        outgoing.entry(key(*a)).or_default().push(id);
+    }
+    let mut used = vec![false; edges.len()];
+    let mut rings = Vec::new();
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+        used[first] = true;
+        let mut ring = vec![edges[first].0];
+        let mut current = first;
+        let closed = loop {
+            let (start, end) = edges[current];
+            if key(end) == key(ring[0]) {
+                break true;
+            }
+            ring.push(end);
+            // Take the sharpest right turn, to split rings touching at a vertex
+            let incoming = sub(end, start);
+            let next = outgoing
+                .get(&key(end))
+                .into_iter()
+                .flatten()
+                .filter(|&&id| !used[id])
+                .min_by(|&&x, &&y| {
+                    let turn = |id: usize| {
+                        let out = sub(edges[id].1, edges[id].0);
+                        cross(incoming, out).atan2(incoming.0 * out.0 + incoming.1 * out.1)
+                    };
+                    turn(x).total_cmp(&turn(y))
+                });
+            match next {
+                Some(&id) => {
+                    used[id] = true;
+                    current = id;
+                }
+                None => break false,
+            }
+        };
+        if closed && ring.len() >= 3 && signed_area(&ring) != 0.0 {
+            rings.push(ring);
+        } else {
+            debug!("Dropping an unclosed ring of {} positions", ring.len());
+        }
+    }
+
+    let (exteriors, holes): (Vec<_>, Vec<_>) =
+        rings.into_iter().partition(|ring| signed_area(ring) > 0.0);
+    let mut polygons: Vec<Vec<Vec<Point>>> = exteriors.into_iter().map(|e| vec![e]).collect();
+    for hole in holes {
+        let (hole_bbox, probe) = (bbox_of(&hole), midpoint(hole[0], hole[1]));
+        let container = polygons
+            .iter()
+            .enumerate()
+            .filter(|(_, polygon)| {
+                let exterior_bbox = bbox_of(&polygon[0]);
+                exterior_bbox[0] <= hole_bbox[0]
+                    && exterior_bbox[1] <= hole_bbox[1]
+                    && exterior_bbox[2] >= hole_bbox[2]
+                    && exterior_bbox[3] >= hole_bbox[3]
+            })
+            .map(|(index, polygon)| (index, signed_area(&polygon[0]), &polygon[0]))
+            .min_by(|x, y| {
+                let contains =
+                    |ring: &Vec<Point>| EdgeIndex::new(std::slice::from_ref(ring)).contains(probe);
+                contains(y.2).cmp(&contains(x.2)).then(x.1.total_cmp(&y.1))
+            })
+            .map(|(index, _, _)| index);
+        match container {
+            Some(index) => polygons[index].push(hole),
+            None => debug!("Dropping a hole outside of the clipped polygons"),
+        }
+    }
+    for ring in polygons.iter_mut().flatten() {
+        ring.push(ring[0]);
+    }
+    polygons
+}
+
+/// Parts of a line inside the mask or along its boundary.
+fn clip_line(line: &[Vec<f64>], mask: &Mask) -> Vec<Vec<Vec<f64>>> {
+    let mut parts = Vec::new();
+    let mut current: Vec<Vec<f64>> = Vec::new();
+    for pair in line.windows(2) {
+        let (a, b) = ((pair[0][0], pair[0][1]), (pair[1][0], pair[1][1]));
+        let mut cuts = vec![0.0, 1.0];
+        for id in mask.index.candidates(a.1.min(b.1), a.1.max(b.1)) {
+            let (c, d) = mask.index.edges[id];
+            cuts.extend(
+                intersections(a, b, c, d)
+                    .into_iter()
+                    .map(|(t, _, _)| t.clamp(0.0, 1.0)),
+            );
+        }
+        cuts.sort_by(f64::total_cmp);
+        cuts.dedup();
+        let at = |t: f64| -> Vec<f64> {
+            match t {
+                0.0 => pair[0].clone(),
+                1.0 => pair[1].clone(),
+                t => pair[0]
+                    .iter()
+                    .zip(&pair[1])
+                    .map(|(a, b)| a + (b - a) * t)
+                    .collect(),
+            }
+        };
+        for span in cuts.windows(2) {
+            let t = (span[0] + span[1]) / 2.0;
+            if mask.covers((a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)) {
+                if current.is_empty() {
+                    current.push(at(span[0]));
+                }
+                current.push(at(span[1]));
+            } else if current.len() >= 2 {
+                parts.push(std::mem::take(&mut current));
+            } else {
+                current.clear();
+            }
+        }
+    }
+    if current.len() >= 2 {
+        parts.push(current);
+    }
+    parts
+}
+
+fn points_to_value(polygons: &[Vec<Vec<Point>>]) -> Value {
+    polygons
+        .iter()
+        .map(|polygon| {
+            polygon
+                .iter()
+                .map(|ring| {
+                    let ring: Vec<Vec<f64>> = ring.iter().map(|p| vec![p.0, p.1]).collect();
+                    positions_to_value(&ring)
+                })
+                .collect::<Array>()
+                .into_value()
+        })
+        .collect::<Array>()
+        .into_value()
+}
+
+/// Clip polygons, given as the coordinates of a MultiPolygon.
+fn clip_polygons(polygons: &Value, mask: &Mask) -> Option<Vec<Vec<Vec<Point>>>> {
+    let mut rings = Vec::new();
+    for polygon in polygons.as_array()?.iter() {
+        polygon_rings(polygon, &mut rings);
+    }
+    match clip_rings(&rings, mask) {
+        ClippedPolygons::Inside => None,
+        ClippedPolygons::Parts(parts) => Some(parts),
+    }
+}
+
+fn set_geometry(geometry: &mut Value, kind: &str, coordinates: Value) {
+    if let Some(object) = geometry.as_object_mut() {
+        object.insert("type", kind);
+        object.insert("coordinates", coordinates);
+    }
+}
+
+/// Intersect a geometry with the mask, returning `false` when nothing is left of it.
+///
+/// Points are kept inside the mask and on its boundary, lines are cut where they
+/// leave the mask, and polygons are intersected with it. A LineString or a Polygon
+/// cut into several parts becomes a MultiLineString or a MultiPolygon.
+/// The positions of cut polygons only keep their two first ordinates.
+pub fn clip_geometry(geometry: &mut Value, mask: &Mask) -> bool {
+    let kind = geometry
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .to_string();
+    if kind == "GeometryCollection" {
+        let Some(geometries) = geometry
+            .get_mut("geometries")
+            .and_then(|g| g.as_array_mut())
+        else {
+            return false;
+        };
+        geometries.retain_mut(|member| clip_geometry(member, mask));
+        return !geometries.is_empty();
+    }
+    let Some(coordinates) = geometry.get_mut("coordinates") else {
+        return false;
+    };
+    match kind.as_str() {
+        "Point" => positions(&Array::from(vec![coordinates.clone()]).into_value())
+            .is_some_and(|p| mask.covers((p[0][0], p[0][1]))),
+        "MultiPoint" => {
+            let Some(points) = coordinates.as_array_mut() else {
+                return false;
+            };
+            points.retain(|point| {
+                positions(&Array::from(vec![point.clone()]).into_value())
+                    .is_some_and(|p| mask.covers((p[0][0], p[0][1])))
+            });
+            !points.is_empty()
+        }
+        "LineString" | "MultiLineString" => {
+            let lines = if kind == "LineString" {
+                vec![coordinates.clone()]
+            } else {
+                coordinates
+                    .as_array()
+                    .map(|a| a.iter().cloned().collect())
+                    .unwrap_or_default()
+            };
+            let lines: Vec<Vec<Vec<f64>>> = lines.iter().filter_map(positions).collect();
+            let parts: Vec<Vec<Vec<f64>>> = lines
+                .iter()
+                .flat_map(|line| clip_line(line, mask))
+                .collect();
+            if parts == lines {
+                return true;
+            }
+            match parts.len() {
+                0 => false,
+                1 if kind == "LineString" => {
+                    *coordinates = positions_to_value(&parts[0]);
+                    true
+                }
+                _ => {
+                    let lines: Array = parts.iter().map(|part| positions_to_value(part)).collect();
+                    set_geometry(geometry, "MultiLineString", lines.into_value());
+                    true
+                }
+            }
+        }
+        "Polygon" | "MultiPolygon" => {
+            let polygons = if kind == "Polygon" {
+                Array::from(vec![coordinates.clone()]).into_value()
+            } else {
+                coordinates.clone()
+            };
+            let Some(parts) = clip_polygons(&polygons, mask) else {
+                return true;
+            };
+            match parts.len() {
+                0 => false,
+                1 if kind == "Polygon" => {
+                    *coordinates = points_to_value(&parts).as_array().unwrap()[0].clone();
+                    true
+                }
+                _ => {
+                    set_geometry(geometry, "MultiPolygon", points_to_value(&parts));
+                    true
+                }
+            }
+        }
+        _ => true,
+    }
+}
+
+/// How much of a geometry lies inside a mask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Coverage {
+    None,
+    Partial,
+    Full,
+}
+
+impl Coverage {
+    /// Coverage of a measure (count, length or area) of which `inside` is in the mask.
+    fn of(inside: f64, total: f64) -> Coverage {
+        if inside <= 0.0 {
+            Coverage::None
+        } else if inside >= total * (1.0 - 1e-9) {
+            Coverage::Full
+        } else {
+            Coverage::Partial
+        }
+    }
+
+    fn combine(coverages: impl IntoIterator<Item = Coverage>) -> Coverage {
+        let (mut none, mut full) = (true, true);
+        for coverage in coverages {
+            none &= coverage == Coverage::None;
+            full &= coverage == Coverage::Full;
+        }
+        match (none, full) {
+            (true, _) => Coverage::None,
+            (false, true) => Coverage::Full,
+            (false, false) => Coverage::Partial,
+        }
+    }
+}
+
+fn line_length(line: &[Vec<f64>]) -> f64 {
+    line.windows(2)
+        .map(|pair| (pair[1][0] - pair[0][0]).hypot(pair[1][1] - pair[0][1]))
+        .sum()
+}
+
+/// Coverage of a geometry by the mask, comparing its points, length or area
+/// with those of its clipped part, so that sharing a border is not an overlap.
+fn coverage(geometry: &Value, mask: &Mask) -> Coverage {
+    let kind = geometry
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default();
+    if kind == "GeometryCollection" {
+        let members = geometry.get("geometries").and_then(|g| g.as_array());
+        return Coverage::combine(members.into_iter().flatten().map(|g| coverage(g, mask)));
+    }
+    let Some(coordinates) = geometry.get("coordinates") else {
+        return Coverage::None;
+    };
+    match kind {
+        "Point" | "MultiPoint" => {
+            let points = if kind == "Point" {
+                positions(&Array::from(vec![coordinates.clone()]).into_value())
+            } else {
+                positions(coordinates)
+            };
+            let points = points.unwrap_or_default();
+            let inside = points.iter().filter(|p| mask.covers((p[0], p[1]))).count();
+            Coverage::of(inside as f64, points.len() as f64)
+        }
+        "LineString" | "MultiLineString" => {
+            let lines: Vec<Vec<Vec<f64>>> = if kind == "LineString" {
+                positions(coordinates).into_iter().collect()
+            } else {
+                coordinates
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(positions)
+                    .collect()
+            };
+            let total: f64 = lines.iter().map(|line| line_length(line)).sum();
+            let inside: f64 = lines
+                .iter()
+                .flat_map(|line| clip_line(line, mask))
+                .map(|part| line_length(&part))
+                .sum();
+            Coverage::of(inside, total)
+        }
+        "Polygon" | "MultiPolygon" => {
+            let mut rings = Vec::new();
+            if kind == "Polygon" {
+                polygon_rings(coordinates, &mut rings);
+            } else {
+                coordinates
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .for_each(|p| polygon_rings(p, &mut rings));
+            }
+            match clip_rings(&rings, mask) {
+                ClippedPolygons::Inside => Coverage::Full,
+                ClippedPolygons::Parts(parts) => Coverage::of(
+                    parts.iter().flatten().map(|ring| signed_area(ring)).sum(),
+                    rings.iter().map(|ring| signed_area(ring)).sum(),
+                ),
+            }
+        }
+        _ => Coverage::None,
+    }
+}
+
+/// A predicate keeping or discarding whole features, without changing their geometry.
+#[derive(Debug, Clone)]
+pub enum SpatialFilter {
+    /// Keep the features entirely inside the mask.
+    Within(Arc<Mask>),
+    /// Keep the features with a part inside the mask;
+    /// lines and polygons only touching its border are discarded.
+    Intersects(Arc<Mask>),
+    /// Keep the features whose bbox overlaps this `[minx, miny, maxx, maxy]` one.
+    Bbox([f64; 4]),
+}
+
+impl SpatialFilter {
+    /// Whether the feature is kept; features without geometry never are.
+    pub fn matches(&self, feature: &Value) -> bool {
+        let Some(geometry) = feature.get("geometry").filter(|g| !g.is_null()) else {
+            return false;
+        };
+        match self {
+            SpatialFilter::Within(mask) => coverage(geometry, mask) == Coverage::Full,
+            SpatialFilter::Intersects(mask) => coverage(geometry, mask) != Coverage::None,
+            SpatialFilter::Bbox(bbox) => compute_geometry_bbox(geometry)
+                .is_some_and(|extent| bboxes_intersect(&extent, bbox)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sonic_rs::{from_str, json};
+
+    fn clipped(geometry: &str, mask: &Mask) -> Option<Value> {
+        let mut geometry: Value = from_str(geometry).unwrap();
+        clip_geometry(&mut geometry, mask).then_some(geometry)
+    }
+
+    fn area(geometry: &Value) -> f64 {
+        let polygons = match geometry["type"].as_str() {
+            Some("Polygon") => vec![geometry["coordinates"].clone()],
+            _ => geometry["coordinates"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .cloned()
+                .collect(),
+        };
+        polygons
+            .iter()
+            .flat_map(|polygon| {
+                polygon
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .map(|ring| {
+                let points: Vec<Point> = positions(&ring)
+                    .unwrap()
+                    .iter()
+                    .map(|p| (p[0], p[1]))
+                    .collect();
+                signed_area(&points)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_clip_polygon_to_bbox() {
+        let mask = Mask::from_bbox([0.0, 0.0, 2.0, 2.0]);
+        let square = clipped(
+            r#"{"type":"Polygon","coordinates":[[[1,1],[3,1],[3,3],[1,3],[1,1]]]}"#,
+            &mask,
+        )
+        .unwrap();
+        assert_eq!(square["type"].as_str(), Some("Polygon"));
+        assert_eq!(area(&square), 1.0);
+
+        let inside =
+            r#"{"type":"Polygon","coordinates":[[[0.5,0.5,10],[1,0.5,10],[1,1,10],[0.5,0.5,10]]]}"#;
+        assert_eq!(
+            clipped(inside, &mask).unwrap(),
+            from_str::<Value>(inside).unwrap()
+        );
+        assert!(clipped(
+            r#"{"type":"Polygon","coordinates":[[[5,5],[6,5],[6,6],[5,5]]]}"#,
+            &mask
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_clip_concave_polygon_into_parts() {
+        // A U shape whose two arms stick out of the bbox
+        let mask = Mask::from_bbox([0.0, 2.0, 3.0, 4.0]);
+        let u = clipped(
+            r#"{"type":"Polygon","coordinates":[[[0,0],[3,0],[3,3],[2,3],[2,1],[1,1],[1,3],[0,3],[0,0]]]}"#,
+            &mask,
+        )
+        .unwrap();
+        assert_eq!(u["type"].as_str(), Some("MultiPolygon"));
+        assert_eq!(u["coordinates"].as_array().unwrap().len(), 2);
+        assert_eq!(area(&u), 2.0);
+    }
+
+    #[test]
+    fn test_clip_polygon_with_hole_to_concave_mask() {
+        let mask = Mask::from_geojson(
+            &from_str(r#"{"type":"Feature","properties":{},"geometry":{"type":"Polygon","coordinates":[[[0,0],[4,0],[4,4],[2,2],[0,4],[0,0]]]}}"#)
+                .unwrap(),
+        )
+        .unwrap();
+        let clipped = clipped(
+            r#"{"type":"Polygon","coordinates":[[[0,0],[4,0],[4,1],[0,1],[0,0]],[[1,0.25],[1,0.75],[3,0.75],[3,0.25],[1,0.25]]]}"#,
+            &mask,
+        )
+        .unwrap();
+        assert_eq!(clipped["type"].as_str(), Some("Polygon"));
+        assert_eq!(clipped["coordinates"].as_array().unwrap().len(), 2);
+        assert_eq!(area(&clipped), 3.0);
+
+        // The mask notch removes the top of a square spanning it
+        let square = clipped_square(&mask);
+        assert!((area(&square) - 12.0).abs() < 1e-12);
+    }
+
+    fn clipped_square(mask: &Mask) -> Value {
+        clipped(
+            r#"{"type":"Polygon","coordinates":[[[0,0],[4,0],[4,4],[0,4],[0,0]]]}"#,
+            mask,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_clip_shared_boundary() {
+        let mask = Mask::from_bbox([0.0, 0.0, 2.0, 2.0]);
+        // Neighbour polygons touching the bbox from outside are removed
+        assert!(clipped(
+            r#"{"type":"Polygon","coordinates":[[[2,0],[3,0],[3,2],[2,2],[2,0]]]}"#,
+            &mask
+        )
+        .is_none());
+        let same = clipped(
+            r#"{"type":"Polygon","coordinates":[[[0,0],[2,0],[2,2],[0,2],[0,0]]]}"#,
+            &mask,
+        )
+        .unwrap();
+        assert_eq!(area(&same), 4.0);
+    }
+
+    #[test]
+    fn test_clip_lines_and_points() {
+        let mask = Mask::from_bbox([0.0, 0.0, 2.0, 2.0]);
+        let line = clipped(
+            r#"{"type":"LineString","coordinates":[[-1,1,5],[1,1,7],[1,3,9],[1.5,3],[1.5,1]]}"#,
+            &mask,
+        )
+        .unwrap();
+        assert_eq!(
+            line,
+            json!({"type": "MultiLineString", "coordinates": [
+                [[0.0, 1.0, 6.0], [1.0, 1.0, 7.0], [1.0, 2.0, 8.0]],
+                [[1.5, 2.0], [1.5, 1.0]]
+            ]})
+        );
+
+        let points = clipped(
+            r#"{"type":"MultiPoint","coordinates":[[1,1],[2,2],[3,3]]}"#,
+            &mask,
+        )
+        .unwrap();
+        assert_eq!(points["coordinates"], json!([[1, 1], [2, 2]]));
+        assert!(clipped(r#"{"type":"Point","coordinates":[3,1]}"#, &mask).is_none());
+        let inside = r#"{"type":"LineString","coordinates":[[0,1],[2,1]]}"#;
+        assert_eq!(
+            clipped(inside, &mask).unwrap(),
+            from_str::<Value>(inside).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_spatial_filters() {
+        let mask = Arc::new(Mask::from_bbox([0.0, 0.0, 2.0, 2.0]));
+        let feature = |geometry: &str| -> Value {
+            from_str(&format!(
+                r#"{{"type":"Feature","properties":{{}},"geometry":{}}}"#,
+                geometry
+            ))
+            .unwrap()
+        };
+        let inside = feature(r#"{"type":"Polygon","coordinates":[[[0,0],[2,0],[1,1],[0,0]]]}"#);
+        let across = feature(r#"{"type":"LineString","coordinates":[[1,1],[3,1]]}"#);
+        let touching = feature(r#"{"type":"Polygon","coordinates":[[[2,0],[3,0],[3,2],[2,0]]]}"#);
+        let points = feature(r#"{"type":"MultiPoint","coordinates":[[1,1],[5,5]]}"#);
+        let empty = feature("null");
+
+        let within = SpatialFilter::Within(mask.clone());
+        let intersects = SpatialFilter::Intersects(mask);
+        let bbox = SpatialFilter::Bbox([2.5, 0.5, 4.0, 4.0]);
+        let kept = |filter: &SpatialFilter| {
+            [&inside, &across, &touching, &points, &empty].map(|feature| filter.matches(feature))
+        };
+        assert_eq!(kept(&within), [true, false, false, false, false]);
+        assert_eq!(kept(&intersects), [true, true, false, true, false]);
+        assert_eq!(kept(&bbox), [false, true, true, true, false]);
+    }
+
+    #[test]
+    fn test_parse_extent() {
+        assert_eq!(
+            "-5.5, 47,-1,49".parse::<Extent>(),
+            Ok(Extent([-5.5, 47.0, -1.0, 49.0]))
+        );
+        assert!("1,2,3".parse::<Extent>().is_err());
+        assert!("3,2,1,4".parse::<Extent>().is_err());
+    }
+
+    #[test]
+    fn test_mask_without_polygon() {
+        let points: Value = from_str(r#"{"type":"Point","coordinates":[3,1]}"#).unwrap();
+        assert!(matches!(
+            Mask::from_geojson(&points),
+            Err(MyError::InvalidGeoJson(_))
+        ));
+    }
+}
@@ -1,19 +1,52 @@
 use crate::args::Args;
-use crate::geo_operations::process_feature;
-use log::{debug, error, info};
+use crate::clip::{Mask, SpatialFilter};
+use crate::geo_operations::{
+    bbox_to_2d, compute_geometry_bbox, geometry_bbox, reproject_geometry, update_bbox, BboxMode,
+    FixSummary, Simplification, SimplifyAlgorithm, VisvalingamThreshold, EMPTY_BBOX,
+};
+use crate::metrics::{CountingReader, CountingWriter, FileSizes};
+use crate::minify::{minify_reader_to_writer, MinifyOptions};
+use crate::pipeline::{FixReport, Pipeline, SimplifyTopology};
+use crate::projection::{Crs, Reprojection};
+use crate::stream::stream_feature_collection;
+use crate::topojson::{geojson_to_topojson, is_topojson, topojson_to_geojson};
+use crate::topology::simplify_geometries_with_topology;
+use clap::ValueEnum;
+use log::{debug, error, info, warn};
 use sonic_rs::{
-    from_str, to_writer, to_writer_pretty, JsonValueMutTrait, JsonValueTrait, Value as SonicValue,
+    from_str, to_writer, to_writer_pretty, JsonContainerTrait, JsonValueMutTrait, JsonValueTrait,
+    Object, Value as SonicValue,
 };
 use std::fs::{self, File};
 use std::io::{self};
-use std::io::{Result as IoResult, Write};
+use std::io::{BufReader, BufWriter, Read, Result as IoResult, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use thiserror::Error;
 
 #[derive(Debug)]
 enum FileExtension {
     GeoJson,
     Json,
+    TopoJson,
+}
+
+/// Format of the output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[value(name = "geojson")]
+    GeoJson,
+    #[value(name = "topojson")]
+    TopoJson,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::GeoJson => "geojson",
+            OutputFormat::TopoJson => "topojson",
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -25,6 +58,9 @@ pub enum MyError {
     #[error("JSON error: {0}")]
     Json(#[from] sonic_rs::Error),
 
+    #[error("JSON stream error: {0}")]
+    JsonStream(#[from] serde_json::Error),
+
     // Error from the program
     // Variants for handle_output_path         1)
     #[error("Invalid filename in the input path")]
@@ -35,33 +71,95 @@ pub enum MyError {
 
     #[error("Failed to create the output directory: {0}")]
     DirectoryCreationError(String),
+
+    #[error("Only one input can be written to stdout")]
+    StdoutWithSeveralInputs,
+
+    // Variants for collect_input_files
+    #[error("Invalid glob pattern: {0}")]
+    InvalidPattern(#[from] glob::PatternError),
+
+    // Variants for the typed GeoJSON model
+    #[error("Invalid GeoJSON: {0}")]
+    InvalidGeoJson(String),
+
+    // Variants for handle_geojson_stream_processing
+    #[error("Streaming does not support {0}")]
+    StreamingNotSupported(String),
+
+    // Variants for the reprojection
+    #[error("Unsupported crs member {0}, set the CRS of the input with --from-crs")]
+    UnsupportedCrs(String),
 }
 
+/// Path standing for stdin as input and stdout as output.
+pub const STDIO: &str = "-";
+
+fn read_to_string<P: AsRef<Path>>(file_path: P) -> io::Result<String> {
+    if file_path.as_ref() == Path::new(STDIO) {
+        let mut content = String::new();
+        io::stdin().lock().read_to_string(&mut content)?;
+        return Ok(content);
+    }
+    fs::read_to_string(&file_path)
+}
+
+fn open_input(input: &str) -> io::Result<Box<dyn Read>> {
+    if input == STDIO {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    Ok(Box::new(File::open(input)?))
+}
+
+fn create_output(output_path: &Path) -> io::Result<Box<dyn Write>> {
+    if output_path == Path::new(STDIO) {
+        return Ok(Box::new(io::stdout().lock()));
+    }
+    Ok(Box::new(File::create(output_path)?))
+}
+
+/// Read and parse a GeoJSON (or TopoJSON) file, or stdin when the path is "-".
 pub fn read_json_file<P: AsRef<Path>>(file_path: P) -> Result<SonicValue, MyError> {
+    let file_content = read_file_content(&file_path)?;
+    parse_json_content(&file_content, &file_path)
+}
+
+pub(crate) fn read_file_content<P: AsRef<Path>>(file_path: P) -> Result<String, MyError> {
     info!("Reading file: {:?}", file_path.as_ref());
 
-    let file_content = fs::read_to_string(&file_path).map_err(|e| {
+    read_to_string(&file_path).map_err(|e| {
         error!(
             "Failed to read file: {:?}, error: {}",
             file_path.as_ref(),
             e
         );
         MyError::Io(e)
-    })?;
+    })
+}
 
-    from_str(&file_content).map_err(|e| {
+pub(crate) fn parse_json_content<P: AsRef<Path>>(
+    file_content: &str,
+    file_path: P,
+) -> Result<SonicValue, MyError> {
+    let json: SonicValue = from_str(file_content).map_err(|e| {
         error!(
             "Failed to parse JSON from file: {:?}, error: {}",
             file_path.as_ref(),
             e
         );
         MyError::Json(e)
-    })
+    })?;
+
+    if is_topojson(&json) {
+        info!("TopoJSON detected, converting to GeoJSON");
+        return Ok(topojson_to_geojson(&json));
+    }
+    Ok(json)
 }
 
-pub fn write_geojson_file(
+pub fn write_geojson_file<W: Write>(
     geojson: &SonicValue,
-    mut output_file: File,
+    mut output_file: W,
     pretty: bool,
 ) -> IoResult<()> {
     info!("Writing GeoJSON file, pretty: {}", pretty);
@@ -79,84 +177,683 @@ pub fn write_geojson_file(
     Ok(())
 }
 
-pub fn process_geojson(
-    geojson: &mut SonicValue,
-    decimal: Option<usize>,
-    remove_null_properties: bool,
-    properties_to_remove: Option<&Vec<String>>,
-    properties_to_keep: Option<&Vec<String>>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    debug!("Processing GeoJSON, with decimal precision: {:?}", decimal);
-    debug!("Remove null properties: {}", remove_null_properties);
-
-    match geojson.get_mut("geometry") {
-        Some(_) => process_feature(
-            geojson,
-            decimal,
-            remove_null_properties,
-            properties_to_remove,
-            properties_to_keep,
-        ),
-        None => {
+pub fn write_topojson_file<W: Write>(
+    geojson: &SonicValue,
+    output_file: W,
+    pretty: bool,
+    object_name: &str,
+    quantization: u32,
+) -> IoResult<()> {
+    info!(
+        "Writing TopoJSON file, object: {}, quantization: {}",
+        object_name, quantization
+    );
+    let topojson = geojson_to_topojson(geojson, object_name, quantization);
+    write_geojson_file(&topojson, output_file, pretty)
+}
+
+/// Number of worker threads for `--jobs`: 0 means one per CPU core.
+pub fn resolve_jobs(jobs: usize) -> usize {
+    if jobs == 0 {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        jobs
+    }
+}
+
+/// Run `f` on every feature, split in contiguous chunks over `jobs` threads.
+/// The features are processed in place, so their order is kept.
+fn for_each_feature<F>(features: &mut [SonicValue], jobs: usize, f: F)
+where
+    F: Fn(usize, &mut SonicValue) + Sync,
+{
+    if jobs <= 1 || features.len() < 2 {
+        for (index, feature) in features.iter_mut().enumerate() {
+            f(index, feature);
+        }
+        return;
+    }
+    let chunk_size = features.len().div_ceil(jobs);
+    debug!(
+        "Processing {} features on {} threads",
+        features.len(),
+        features.len().div_ceil(chunk_size)
+    );
+    std::thread::scope(|scope| {
+        for (chunk_index, chunk) in features.chunks_mut(chunk_size).enumerate() {
+            let f = &f;
+            scope.spawn(move || {
+                for (index, feature) in chunk.iter_mut().enumerate() {
+                    f(chunk_index * chunk_size + index, feature);
+                }
+            });
+        }
+    });
+}
+
+pub fn process_geojson(geojson: &mut SonicValue, options: &MinifyOptions) -> Result<(), MyError> {
+    debug!("Processing GeoJSON with options: {:?}", options);
+
+    let from_crs = document_crs(geojson, options.from_crs)?;
+    let reprojected = from_crs != options.to_crs;
+    let options = &MinifyOptions {
+        from_crs: Some(from_crs),
+        ..options.clone()
+    };
+
+    let pipeline = Pipeline::from(options);
+    let filter = FeatureFilter::new(options);
+    let geojson_type = match geojson.get("type").and_then(|t| t.as_str()) {
+        Some(geojson_type) => geojson_type.to_string(),
+        // A document without a type is processed as a collection when it has features
+        None if geojson.get("features").is_some_and(|f| f.is_array()) => {
+            "FeatureCollection".to_string()
+        }
+        None => String::new(),
+    };
+    match geojson_type.as_str() {
+        "FeatureCollection" => {
+            let mut removed = false;
             if let Some(features) = geojson.get_mut("features").and_then(|f| f.as_array_mut()) {
-                for feature in features.iter_mut() {
-                    process_feature(
-                        feature,
-                        decimal,
-                        remove_null_properties,
-                        properties_to_remove,
-                        properties_to_keep,
-                    );
+                let count = features.len();
+                let jobs = resolve_jobs(options.jobs);
+                // Shared borders are simplified once for the whole collection,
+                // between the steps before and after the simplification
+                match pipeline.split_at(SimplifyTopology::NAME) {
+                    Some((before, after)) => {
+                        for_each_feature(features, jobs, |index, feature| {
+                            filter.apply_at(&before, index, feature)
+                        });
+                        if let Some(simplification) = options.simplification {
+                            let mut geometries: Vec<&mut SonicValue> = features
+                                .iter_mut()
+                                .filter_map(|feature| feature.get_mut("geometry"))
+                                .collect();
+                            simplify_geometries_with_topology(
+                                &mut geometries,
+                                &simplification.for_crs(options.to_crs),
+                            );
+                        }
+                        for_each_feature(features, jobs, |index, feature| {
+                            if !feature.is_null() {
+                                after.apply_at(index, feature)
+                            }
+                        });
+                    }
+                    None => for_each_feature(features, jobs, |index, feature| {
+                        filter.apply_at(&pipeline, index, feature)
+                    }),
                 }
+                let before = features.len();
+                features.retain(|feature| !feature.is_null());
+                if features.len() < before {
+                    info!("{} features removed", before - features.len());
+                }
+                removed = features.len() < count;
+            }
+            if options.force_2d {
+                bbox_to_2d(geojson);
+            }
+            // A kept collection bbox is still in the input CRS,
+            // or covers clipped or removed features
+            let changed = reprojected || options.clip.is_some() || removed;
+            let mode = match options.bbox {
+                BboxMode::Keep if changed && geojson.get("bbox").is_some() => BboxMode::Compute,
+                mode => mode,
+            };
+            update_bbox(geojson, mode, &options.rounding(), collection_bbox);
+        }
+        "Feature" => {
+            filter.apply_at(&pipeline, 0, geojson);
+            if geojson.is_null() {
+                warn!("The feature is removed, an empty FeatureCollection is written");
+                *geojson = empty_feature_collection();
+            }
+        }
+        geometry_type if GEOMETRY_TYPES.contains(&geometry_type) => {
+            // The transforms work on features, so a bare geometry is wrapped in one
+            debug!("Processing a bare {}", geometry_type);
+            let mut feature = Object::new();
+            feature.insert("type", "Feature");
+            feature.insert("geometry", std::mem::take(geojson));
+            let mut feature = feature.into_value();
+            filter.apply_at(&pipeline, 0, &mut feature);
+            if feature.is_null() {
+                warn!("The geometry is removed, an empty FeatureCollection is written");
+                *geojson = empty_feature_collection();
+                return Ok(());
+            }
+            *geojson = feature["geometry"].take();
+            if options.bbox == BboxMode::Compute {
+                update_bbox(
+                    geojson,
+                    options.bbox,
+                    &options.rounding(),
+                    compute_geometry_bbox,
+                );
             }
         }
+        other => {
+            return Err(MyError::InvalidGeoJson(format!(
+                "nothing to process in a GeoJSON of type {:?}",
+                other
+            )));
+        }
     }
+    report_fixes(&pipeline.fixes());
+    update_crs_member(geojson, options.to_crs);
 
     Ok(())
 }
 
+/// What is left of a document whose only feature is removed.
+fn empty_feature_collection() -> SonicValue {
+    let mut collection = Object::new();
+    collection.insert("type", "FeatureCollection");
+    collection.insert("features", SonicValue::new_array());
+    collection.into_value()
+}
+
+/// RFC 7946 removed the `crs` member: coordinates are WGS84.
+/// It is only kept to name another CRS asked for explicitly.
+/// The CRS of a document: `from_crs` when given, otherwise the one of its legacy `crs` member.
+/// A `crs` member naming an unsupported CRS is an error, as its coordinates cannot be converted.
+fn document_crs(geojson: &SonicValue, from_crs: Option<Crs>) -> Result<Crs, MyError> {
+    if let Some(crs) = from_crs {
+        return Ok(crs);
+    }
+    match geojson.get("crs") {
+        Some(crs) if !crs.is_null() => {
+            Crs::from_crs_member(crs).ok_or_else(|| MyError::UnsupportedCrs(crs.to_string()))
+        }
+        _ => Ok(Crs::Wgs84),
+    }
+}
+
+fn update_crs_member(geojson: &mut SonicValue, crs: Crs) {
+    let Some(object) = geojson.as_object_mut() else {
+        return;
+    };
+    if crs == Crs::Wgs84 {
+        object.remove(&"crs");
+    } else {
+        object.insert("crs", crs.crs_member());
+    }
+}
+
+/// The bbox of all the features of a collection, `None` when it has no position.
+fn collection_bbox(collection: &SonicValue) -> Option<[f64; 4]> {
+    let mut bbox = EMPTY_BBOX;
+    for feature in collection
+        .get("features")
+        .and_then(|f| f.as_array())
+        .into_iter()
+        .flatten()
+    {
+        if let Some(geometry) = feature.get("geometry") {
+            geometry_bbox(geometry, &mut bbox);
+        }
+    }
+    bbox[0].is_finite().then_some(bbox)
+}
+
+/// Print the repairs made by `--fix`, on stderr so that they never mix with the output.
+fn report_fixes(fixes: &FixReport) {
+    if fixes.is_empty() {
+        return;
+    }
+    let mut total = FixSummary::default();
+    for (index, summary) in fixes {
+        eprintln!("Fixed feature {}: {}", index, summary);
+        total.add(summary);
+    }
+    eprintln!("Fixed {} features: {}", fixes.len(), total);
+}
+
+/// An input file, with the directory its output is mirrored in (relative to the
+/// input directory it was found in, or to the non-wildcard prefix of its glob pattern).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputFile {
+    pub path: String,
+    pub relative_dir: PathBuf,
+}
+
+impl InputFile {
+    pub fn new(path: &str) -> Self {
+        InputFile {
+            path: path.to_string(),
+            relative_dir: PathBuf::new(),
+        }
+    }
+
+    /// A file found under `root`, whose output mirrors its directory relative to `root`.
+    fn under(root: &Path, path: &Path) -> Self {
+        let relative_dir = path
+            .parent()
+            .and_then(|parent| parent.strip_prefix(root).ok())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        InputFile {
+            path: path.to_string_lossy().to_string(),
+            relative_dir,
+        }
+    }
+}
+
+fn is_glob_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+fn collect_directory_files(
+    root: &Path,
+    directory: &Path,
+    recursive: bool,
+    files: &mut Vec<InputFile>,
+) -> Result<(), MyError> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            if recursive {
+                collect_directory_files(root, &path, recursive, files)?;
+            }
+        } else if extract_filename_from_path(&path.to_string_lossy()).is_some() {
+            files.push(InputFile::under(root, &path));
+        }
+    }
+    Ok(())
+}
+
+/// The directories of a glob pattern before its first wildcard.
+fn glob_root(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|component| !is_glob_pattern(&component.as_os_str().to_string_lossy()))
+        .collect()
+}
+
+/// Expand the `--input` values into the list of files to process.
+/// Directories give their GeoJSON, JSON and TopoJSON files (recursively with `--recursive`),
+/// glob patterns give the files they match, and other values are kept as is.
+pub fn collect_input_files(args: &Args) -> Result<Vec<InputFile>, MyError> {
+    let mut files = Vec::new();
+    for input in &args.input {
+        let path = Path::new(input);
+        if path.is_dir() {
+            debug!("Collecting files from directory: {}", input);
+            collect_directory_files(path, path, args.recursive, &mut files)?;
+        } else if !path.exists() && is_glob_pattern(input) {
+            debug!("Collecting files matching pattern: {}", input);
+            let root = glob_root(input);
+            for entry in glob::glob(input)? {
+                match entry {
+                    Ok(path) if path.is_file() => files.push(InputFile::under(&root, &path)),
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to read glob entry: {}", e),
+                }
+            }
+        } else {
+            files.push(InputFile::new(input));
+        }
+    }
+    info!("{} input files to process", files.len());
+    Ok(files)
+}
+
+/// Whether the inputs are processed as a batch: the output is then always a directory.
+pub fn is_batch(args: &Args, files: &[InputFile]) -> bool {
+    files.len() > 1
+        || args
+            .input
+            .iter()
+            .any(|input| Path::new(input).is_dir() || is_glob_pattern(input))
+}
+
+/// Process one input file and return the sizes of the input and of the output.
+pub fn handle_input_file(
+    args: &Args,
+    input: &InputFile,
+    batch: bool,
+) -> Result<FileSizes, Box<dyn std::error::Error>> {
+    let output_path = handle_output_path(args, input, batch)?;
+    handle_geojson_processing(args, &input.path, &output_path)
+}
+
 pub fn handle_geojson_processing(
     args: &Args,
+    input: &str,
     output_path: &PathBuf,
-) -> Result<(), Box<dyn std::error::Error>> {
-    info!("Handling GeoJSON processing for file: {:?}", args.input);
-    let mut geojson = read_json_file(&args.input)?;
+) -> Result<FileSizes, Box<dyn std::error::Error>> {
+    if args.stream {
+        return Ok(handle_geojson_stream_processing(args, input, output_path)?);
+    }
+    info!("Handling GeoJSON processing for file: {:?}", input);
+    let file_content = read_file_content(input)?;
+    let original_size = file_content.len() as u64;
 
-    let decimal = if args.remove_null_properties && args.decimal.unwrap_or_default() != 0 {
-        Some(args.decimal.unwrap_or_default())
-    } else {
-        None
-    };
+    let mut options = MinifyOptions::from(args);
+    load_masks(args, &mut options)?;
+    if let Some(object_name) = Path::new(input)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .filter(|_| input != STDIO)
+    {
+        options.object_name = object_name.to_string();
+    }
+
+    let mut file = CountingWriter::new(create_output(output_path)?);
+    minify_reader_to_writer(file_content.as_bytes(), &mut file, &options)?;
+    info!("GeoJSON written successfully to {:?}", output_path);
 
-    process_geojson(
-        &mut geojson,
-        decimal,
-        args.remove_null_properties,
-        args.properties_to_remove.as_ref(),
-        args.properties_to_keep.as_ref(),
+    Ok(FileSizes {
+        name: input.to_string(),
+        original_size,
+        minified_size: file.count(),
+    })
+}
+
+/// Process the features of the input one at a time, without loading the whole file.
+/// Only the steps that work on a single feature are available.
+pub fn handle_geojson_stream_processing(
+    args: &Args,
+    input: &str,
+    output_path: &PathBuf,
+) -> Result<FileSizes, MyError> {
+    info!("Handling GeoJSON stream processing for file: {:?}", input);
+    if args.preserve_topology {
+        return Err(MyError::StreamingNotSupported(
+            "--preserve-topology".to_string(),
+        ));
+    }
+    if output_format(args) == OutputFormat::TopoJson {
+        return Err(MyError::StreamingNotSupported(
+            "TopoJSON output".to_string(),
+        ));
+    }
+    // The collection bbox may be written before the features it depends on
+    if args.bbox != BboxMode::Keep {
+        return Err(MyError::StreamingNotSupported(format!(
+            "--bbox {}",
+            args.bbox
+                .to_possible_value()
+                .map_or_else(String::new, |v| v.get_name().to_string())
+        )));
+    }
+    if args.from_crs.is_some() || args.to_crs != Crs::Wgs84 {
+        return Err(MyError::StreamingNotSupported("reprojection".to_string()));
+    }
+    if args.pretty {
+        warn!("--pretty is ignored when streaming");
+    }
+
+    let mut options = MinifyOptions::from(args);
+    load_masks(args, &mut options)?;
+    let streamed = stream_geojson(input, output_path, &options);
+    // The members are checked as they are written, a failure leaves a partial document
+    if streamed.is_err() && output_path != Path::new(STDIO) && output_path.exists() {
+        if let Err(e) = fs::remove_file(output_path) {
+            warn!(
+                "Failed to remove the partial output {:?}: {}",
+                output_path, e
+            );
+        }
+    }
+    streamed
+}
+
+fn stream_geojson(
+    input: &str,
+    output_path: &Path,
+    options: &MinifyOptions,
+) -> Result<FileSizes, MyError> {
+    let pipeline = Pipeline::from(options);
+    let filter = FeatureFilter::new(options);
+    let mut reader = CountingReader::new(BufReader::new(open_input(input)?));
+    let mut writer = CountingWriter::new(BufWriter::new(create_output(output_path)?));
+    let mut index = 0;
+    let mut is_collection = false;
+    let count = stream_feature_collection(
+        &mut reader,
+        &mut writer,
+        |feature| {
+            filter.apply_at(&pipeline, index, feature);
+            index += 1;
+        },
+        |key, value| check_streamed_member(key, value, options, &mut is_collection),
     )?;
-    info!("GeoJSON processed successfully.");
+    if !is_collection {
+        return Err(MyError::StreamingNotSupported(
+            "a GeoJSON without the FeatureCollection type".to_string(),
+        ));
+    }
+    report_fixes(&pipeline.fixes());
+    info!(
+        "{} features streamed successfully to {:?}",
+        count, output_path
+    );
 
-    let file = File::create(output_path)?;
-    write_geojson_file(&geojson, file, args.pretty)?;
-    info!("GeoJSON written successfully to {:?}", output_path);
+    Ok(FileSizes {
+        name: input.to_string(),
+        original_size: reader.count(),
+        minified_size: writer.count(),
+    })
+}
+
+/// Check a member of a streamed document other than `features`, and whether to write it:
+/// only WGS84 FeatureCollections can be streamed. A kept bbox is rounded, or removed
+/// when the features it covers may change, as it can be written before them.
+fn check_streamed_member(
+    key: &str,
+    value: &mut SonicValue,
+    options: &MinifyOptions,
+    is_collection: &mut bool,
+) -> Result<bool, MyError> {
+    match key {
+        "type" => match value.as_str() {
+            Some("FeatureCollection") => *is_collection = true,
+            other => {
+                return Err(MyError::StreamingNotSupported(format!(
+                    "a GeoJSON of type {:?}",
+                    other.unwrap_or_default()
+                )))
+            }
+        },
+        // Without --from-crs, the in-memory processing would follow the crs member
+        "crs" if !value.is_null() => match Crs::from_crs_member(value) {
+            Some(Crs::Wgs84) => {}
+            Some(crs) => {
+                return Err(MyError::StreamingNotSupported(format!(
+                    "the EPSG:{} crs member",
+                    crs.epsg_code()
+                )))
+            }
+            None => {
+                return Err(MyError::StreamingNotSupported(format!(
+                    "the unsupported crs member {}",
+                    value
+                )))
+            }
+        },
+        "bbox"
+            if options.min_feature_area.is_some()
+                || options.clip.is_some()
+                || !options.spatial_filters.is_empty() =>
+        {
+            warn!("The collection bbox is removed, as features may be clipped or removed when streaming");
+            return Ok(false);
+        }
+        "bbox" => {
+            let mut object = Object::new();
+            object.insert("bbox", std::mem::take(value));
+            let mut object = object.into_value();
+            update_bbox(&mut object, options.bbox, &options.rounding(), |_| None);
+            *value = object["bbox"].take();
+        }
+        _ => {}
+    }
+    Ok(true)
+}
 
+/// Read the mask files of the clip and of the spatial filters into `options`.
+fn load_masks(args: &Args, options: &mut MinifyOptions) -> Result<(), MyError> {
+    if let Some(path) = &args.clip_mask {
+        options.clip = Some(read_mask(path, args.to_crs)?);
+    }
+    if let Some(path) = &args.within {
+        let mask = read_mask(path, args.to_crs)?;
+        options.spatial_filters.push(SpatialFilter::Within(mask));
+    }
+    if let Some(path) = &args.intersects {
+        let mask = read_mask(path, args.to_crs)?;
+        options
+            .spatial_filters
+            .push(SpatialFilter::Intersects(mask));
+    }
     Ok(())
 }
 
-pub fn handle_output_path(args: &Args) -> Result<PathBuf, MyError> {
-    let sanitized_output = sanitize_output_path(&args.output);
+/// The polygons of a GeoJSON file, converted to the output CRS.
+fn read_mask(path: &Path, to_crs: Crs) -> Result<Arc<Mask>, MyError> {
+    let mut mask = read_json_file(path)?;
+    let from = document_crs(&mask, None)?;
+    if from != to_crs {
+        debug!("Reprojecting the mask {:?} from {:?}", path, from);
+        let reprojection = Reprojection::new(from, to_crs);
+        let mut geometries: Vec<&mut SonicValue> = match mask.get("type").and_then(|t| t.as_str()) {
+            Some("FeatureCollection") => mask
+                .get_mut("features")
+                .and_then(|f| f.as_array_mut())
+                .map(|features| {
+                    features
+                        .iter_mut()
+                        .filter_map(|feature| feature.get_mut("geometry"))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Some("Feature") => mask.get_mut("geometry").into_iter().collect(),
+            _ => vec![&mut mask],
+        };
+        for geometry in geometries.iter_mut() {
+            reproject_geometry(geometry, &reprojection);
+        }
+    }
+    Ok(Arc::new(Mask::from_geojson(&mask)?))
+}
+
+/// The spatial filters of the options, tested on the input geometry of each feature
+/// (reprojected to the output CRS, like the masks) before the pipeline clips or simplifies it.
+struct FeatureFilter<'a> {
+    filters: &'a [SpatialFilter],
+    reprojection: Option<Reprojection>,
+}
+
+impl<'a> FeatureFilter<'a> {
+    fn new(options: &'a MinifyOptions) -> Self {
+        FeatureFilter {
+            filters: &options.spatial_filters,
+            reprojection: options
+                .from_crs
+                .filter(|from_crs| *from_crs != options.to_crs)
+                .map(|from_crs| Reprojection::new(from_crs, options.to_crs)),
+        }
+    }
+
+    fn matches(&self, feature: &SonicValue) -> bool {
+        let Some(reprojection) = &self.reprojection else {
+            return self.filters.iter().all(|filter| filter.matches(feature));
+        };
+        let mut reprojected = Object::new();
+        if let Some(geometry) = feature.get("geometry") {
+            let mut geometry = geometry.clone();
+            reproject_geometry(&mut geometry, reprojection);
+            reprojected.insert("geometry", geometry);
+        }
+        let reprojected = reprojected.into_value();
+        self.filters
+            .iter()
+            .all(|filter| filter.matches(&reprojected))
+    }
+
+    /// Apply `pipeline` to a matching feature, and remove the other ones.
+    fn apply_at(&self, pipeline: &Pipeline, index: usize, feature: &mut SonicValue) {
+        if self.filters.is_empty() || self.matches(feature) {
+            pipeline.apply_at(index, feature);
+        } else {
+            *feature = SonicValue::new();
+        }
+    }
+}
+
+pub(crate) fn simplification_from_args(args: &Args) -> Option<Simplification> {
+    let unit = args.simplify_unit;
+    match (args.simplify_algorithm, args.simplify, args.simplify_keep) {
+        (SimplifyAlgorithm::DouglasPeucker, Some(tolerance), _) => {
+            Some(Simplification::DouglasPeucker { tolerance, unit })
+        }
+        (SimplifyAlgorithm::Visvalingam, Some(area), _) => Some(Simplification::Visvalingam {
+            threshold: VisvalingamThreshold::Area(area),
+            unit,
+        }),
+        (SimplifyAlgorithm::Visvalingam, None, Some(percentage)) => {
+            Some(Simplification::Visvalingam {
+                threshold: VisvalingamThreshold::KeepPercentage(percentage),
+                unit,
+            })
+        }
+        // Rejected by `Args::check`
+        (SimplifyAlgorithm::DouglasPeucker, None, Some(_)) => {
+            error!("--simplify-keep is only supported by the visvalingam algorithm");
+            None
+        }
+        (_, None, None) => None,
+    }
+}
+
+/// Output format from `--format`, or from the output file extension.
+pub fn output_format(args: &Args) -> OutputFormat {
+    args.format.unwrap_or_else(|| {
+        if args.output.ends_with(".topojson") {
+            OutputFormat::TopoJson
+        } else {
+            OutputFormat::GeoJson
+        }
+    })
+}
+
+/// Output path of an input file. In batch mode, the output is a directory
+/// where the inputs found in directories keep their relative location.
+pub fn handle_output_path(args: &Args, input: &InputFile, batch: bool) -> Result<PathBuf, MyError> {
+    if args.writes_to_stdout() {
+        if batch {
+            return Err(MyError::StdoutWithSeveralInputs);
+        }
+        info!("Output path: stdout");
+        return Ok(PathBuf::from(STDIO));
+    }
+    let format = output_format(args);
+    let is_directory = batch || args.output.ends_with('/');
+    let output = if is_directory {
+        format!("{}/", args.output.trim_end_matches('/'))
+    } else {
+        args.output.clone()
+    };
+    let sanitized_output = sanitize_output_path(&output, format);
     let mut output_path = PathBuf::from(sanitized_output);
 
-    if output_path == PathBuf::from("./output/") || args.output.ends_with('/') {
-        if let Some(filename) = extract_filename_from_path(&args.input) {
-            output_path.push(format!("min_{}", filename));
+    if is_directory {
+        if let Some(filename) = extract_filename_from_path(&input.path) {
+            let filename = Path::new(&filename).with_extension(format.extension());
+            output_path.push(&input.relative_dir);
+            output_path.push(format!("min_{}", filename.to_string_lossy()));
             info!(
                 "Output path set to default, filename adjusted: {:?}",
                 output_path
             );
         } else {
-            error!("Invalid filename in input path: {}", args.input);
+            error!("Invalid filename in input path: {}", input.path);
             return Err(MyError::InvalidFilename);
         }
     }
@@ -180,22 +877,26 @@ pub fn handle_output_path(args: &Args) -> Result<PathBuf, MyError> {
     Ok(output_path)
 }
 
-fn sanitize_output_path(output_path: &str) -> String {
+/// Replace the extension of the output path by the one of the format.
+/// Directories (ending with '/') are left without extension.
+fn sanitize_output_path(output_path: &str, format: OutputFormat) -> String {
     let sanitized: String = output_path
         .chars()
         .filter(|&c| !matches!(c, '?' | '%' | '*' | ':' | '|' | '"' | '<' | '>'))
         .map(|c| if c == ' ' { '_' } else { c })
         .collect();
 
-    let trimmed = sanitized.trim_end_matches(|c| c == '-' || c == '_');
+    let trimmed = sanitized.trim_end_matches(['-', '_']);
 
-    if trimmed.ends_with(".geojson") {
-        trimmed.to_string()
-    } else if trimmed.ends_with(".json") {
-        format!("{}.geojson", trimmed.trim_end_matches(".json"))
-    } else {
-        format!("{}.geojson", trimmed)
+    let extension = format.extension();
+    if trimmed.ends_with('/') {
+        return trimmed.to_string();
     }
+    let stem = [".geojson", ".topojson", ".json"]
+        .iter()
+        .find_map(|known| trimmed.strip_suffix(known))
+        .unwrap_or(trimmed);
+    format!("{}.{}", stem, extension)
 }
 
 fn extract_file_extension(ext: &str) -> Option<FileExtension> {
@@ -203,6 +904,7 @@ fn extract_file_extension(ext: &str) -> Option<FileExtension> {
     let extension = match ext {
         "geojson" => Some(FileExtension::GeoJson),
         "json" => Some(FileExtension::Json),
+        "topojson" => Some(FileExtension::TopoJson),
         _ => None,
     };
 
@@ -241,23 +943,58 @@ pub fn add_prefix_to_filename(filename: &str, prefix: &str) -> String {
 
 pub fn is_geojson(parsed_json: &SonicValue) -> bool {
     debug!("Checking if parsed JSON is GeoJSON: {}", parsed_json);
-    let is_geojson = if let Some(geometry) = parsed_json.get("geometry") {
-        if let Some(coordinates) = geometry.get("coordinates") {
-            // Check if 'coordinates' is an array and not null or another type
-            coordinates.is_array() && !coordinates.is_null()
-        } else {
-            false
-        }
-    } else {
-        false
+    let is_geojson = match parsed_json.get("type").and_then(|t| t.as_str()) {
+        Some("FeatureCollection") => parsed_json
+            .get("features")
+            .and_then(|features| features.as_array())
+            .is_some_and(|features| features.iter().all(is_feature)),
+        Some("Feature") => is_feature(parsed_json),
+        _ => is_geometry(parsed_json),
     };
     info!("Is GeoJSON: {}", is_geojson);
     is_geojson
 }
 
+/// RFC 7946 geometry types.
+pub const GEOMETRY_TYPES: [&str; 7] = [
+    "Point",
+    "MultiPoint",
+    "LineString",
+    "MultiLineString",
+    "Polygon",
+    "MultiPolygon",
+    "GeometryCollection",
+];
+
+fn is_feature(feature: &SonicValue) -> bool {
+    feature.get("type").and_then(|t| t.as_str()) == Some("Feature")
+        && feature
+            .get("geometry")
+            .is_some_and(|geometry| geometry.is_null() || is_geometry(geometry))
+}
+
+fn is_geometry(geometry: &SonicValue) -> bool {
+    match geometry.get("type").and_then(|t| t.as_str()) {
+        Some("GeometryCollection") => geometry
+            .get("geometries")
+            .and_then(|geometries| geometries.as_array())
+            .is_some_and(|geometries| geometries.iter().all(is_geometry)),
+        Some(geometry_type) if GEOMETRY_TYPES.contains(&geometry_type) => {
+            // Check if 'coordinates' is an array and not null or another type
+            geometry
+                .get("coordinates")
+                .is_some_and(|coordinates| coordinates.is_array())
+        }
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::geo_operations::{geometry_area, ToleranceUnit};
+    use crate::topology::{build_topology, geometry_lines};
     use clap::Parser;
+    use std::collections::HashSet;
     // use serde_json::json;
     // use std::io::{Read, Seek, SeekFrom};
     // use tempfile::NamedTempFile;
@@ -266,7 +1003,7 @@ mod tests {
     #[test]
     fn test_base_arg_parsing_with_short_arg() {
         let args = Args::parse_from(["mini-geojson", "-i", "input.geojson", "-d", "3"]);
-        assert_eq!(args.input, "input.geojson");
+        assert_eq!(args.input, vec!["input.geojson"]);
         assert_eq!(args.output, "./output/");
         assert_eq!(args.decimal, Some(3));
         assert!(!args.overwrite);
@@ -286,7 +1023,7 @@ mod tests {
             "-O",
             "-p",
         ]);
-        assert_eq!(args.input, "input.geojson");
+        assert_eq!(args.input, vec!["input.geojson"]);
         assert_eq!(args.output, "output.geojson");
         assert_eq!(args.decimal, Some(3));
         assert!(args.overwrite);
@@ -296,7 +1033,7 @@ mod tests {
     #[test]
     fn test_base_arg_parsing_with_long_arg() {
         let args = Args::parse_from(["mini-geojson", "--input", "input.geojson", "--decimal", "3"]);
-        assert_eq!(args.input, "input.geojson");
+        assert_eq!(args.input, vec!["input.geojson"]);
         assert_eq!(args.output, "./output/");
         assert_eq!(args.decimal, Some(3));
         assert!(!args.overwrite);
@@ -316,13 +1053,151 @@ mod tests {
             "--overwrite",
             "--pretty",
         ]);
-        assert_eq!(args.input, "input.geojson");
+        assert_eq!(args.input, vec!["input.geojson"]);
         assert_eq!(args.output, "output.geojson");
         assert_eq!(args.decimal, Some(3));
         assert!(args.overwrite);
         assert!(args.pretty);
     }
 
+    #[test]
+    fn test_simplification_from_args_with_visvalingam_keep() {
+        let args = Args::parse_from([
+            "mini-geojson",
+            "-i",
+            "input.geojson",
+            "--simplify-algorithm",
+            "visvalingam",
+            "--simplify-keep",
+            "10",
+        ]);
+        assert_eq!(
+            simplification_from_args(&args),
+            Some(Simplification::Visvalingam {
+                threshold: VisvalingamThreshold::KeepPercentage(10.0),
+                unit: ToleranceUnit::Degrees,
+            })
+        );
+    }
+
+    #[test]
+    fn test_sanitize_output_path_for_geojson() {
+        assert_eq!(
+            sanitize_output_path("out put.json", OutputFormat::GeoJson),
+            "out_put.geojson"
+        );
+        assert_eq!(
+            sanitize_output_path("./output/", OutputFormat::GeoJson),
+            "./output/"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_output_path_for_topojson() {
+        assert_eq!(
+            sanitize_output_path("output.geojson", OutputFormat::TopoJson),
+            "output.topojson"
+        );
+        assert_eq!(
+            sanitize_output_path("output", OutputFormat::TopoJson),
+            "output.topojson"
+        );
+    }
+
+    #[test]
+    fn test_output_format_from_output_extension() {
+        let args = Args::parse_from(["mini-geojson", "-i", "input.geojson", "-o", "out.topojson"]);
+        assert_eq!(output_format(&args), OutputFormat::TopoJson);
+        let args = Args::parse_from(["mini-geojson", "-i", "input.geojson", "-o", "out.json"]);
+        assert_eq!(output_format(&args), OutputFormat::GeoJson);
+    }
+
+    #[test]
+    fn test_input_parsing_with_several_files() {
+        let args = Args::parse_from(["mini-geojson", "-i", "a.geojson", "b.geojson", "-d", "3"]);
+        assert_eq!(args.input, vec!["a.geojson", "b.geojson"]);
+        assert_eq!(args.decimal, Some(3));
+    }
+
+    #[test]
+    fn test_collect_input_files_from_directory_and_pattern() {
+        let directory = tempfile::tempdir().unwrap();
+        let nested = directory.path().join("nested");
+        fs::create_dir(&nested).unwrap();
+        fs::write(directory.path().join("a.geojson"), "{}").unwrap();
+        fs::write(directory.path().join("notes.txt"), "").unwrap();
+        fs::write(nested.join("b.json"), "{}").unwrap();
+        let root = directory.path().to_string_lossy().to_string();
+
+        let args = Args::parse_from(["mini-geojson", "-i", &root]);
+        let files = collect_input_files(&args).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].path.ends_with("a.geojson"));
+
+        let args = Args::parse_from(["mini-geojson", "-i", &root, "--recursive"]);
+        let files = collect_input_files(&args).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1].relative_dir, PathBuf::from("nested"));
+
+        let pattern = format!("{}/*/*.json", root);
+        let args = Args::parse_from(["mini-geojson", "-i", &pattern]);
+        let files = collect_input_files(&args).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].relative_dir, PathBuf::from("nested"));
+        assert!(is_batch(&args, &files));
+    }
+
+    #[test]
+    fn test_collect_input_files_mirrors_the_directories_of_glob_matches() {
+        let directory = tempfile::tempdir().unwrap();
+        for name in ["paris", "lyon"] {
+            fs::create_dir(directory.path().join(name)).unwrap();
+            fs::write(directory.path().join(name).join("communes.geojson"), "{}").unwrap();
+        }
+        let pattern = format!("{}/*/communes.geojson", directory.path().display());
+
+        let args = Args::parse_from(["mini-geojson", "-i", &pattern]);
+        let files = collect_input_files(&args).unwrap();
+
+        let relative_dirs: Vec<&Path> = files.iter().map(|f| f.relative_dir.as_path()).collect();
+        assert_eq!(relative_dirs, [Path::new("lyon"), Path::new("paris")]);
+        assert_eq!(glob_root("data/*/communes.geojson"), PathBuf::from("data"));
+        assert_eq!(glob_root("*.geojson"), PathBuf::new());
+    }
+
+    #[test]
+    fn test_handle_output_path_mirrors_input_directories() {
+        let directory = tempfile::tempdir().unwrap();
+        let output = directory.path().join("out").to_string_lossy().to_string();
+        let args = Args::parse_from(["mini-geojson", "-i", "in", "-o", &output]);
+        let input = InputFile {
+            path: "in/nested/a.json".to_string(),
+            relative_dir: PathBuf::from("nested"),
+        };
+
+        let output_path = handle_output_path(&args, &input, true).unwrap();
+
+        assert_eq!(
+            output_path,
+            directory.path().join("out/nested/min_a.geojson")
+        );
+    }
+
+    #[test]
+    fn test_handle_output_path_for_stdout() {
+        let args = Args::parse_from(["mini-geojson", "-i", "-", "-o", "-"]);
+        let input = InputFile::new("-");
+
+        assert_eq!(
+            handle_output_path(&args, &input, false).unwrap(),
+            PathBuf::from("-")
+        );
+        assert!(matches!(
+            handle_output_path(&args, &input, true),
+            Err(MyError::StdoutWithSeveralInputs)
+        ));
+    }
+
     #[test]
     fn test_extract_filename_from_path_with_no_filename_given() {
         let filename = extract_filename_from_path("/home/user/");
@@ -371,11 +1246,490 @@ mod tests {
         assert!(!is_geojson);
     }
 
+    #[test]
+    fn test_is_geojson_for_every_top_level_type() {
+        for geojson in [
+            r#"{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,0]]]}"#,
+            r#"{"type":"GeometryCollection","geometries":[{"type":"Point","coordinates":[0,0]},{"type":"GeometryCollection","geometries":[]}]}"#,
+            r#"{"type":"Feature","properties":null,"geometry":null}"#,
+            r#"{"type":"FeatureCollection","features":[{"type":"Feature","properties":{},"geometry":{"type":"MultiPoint","coordinates":[]}}]}"#,
+        ] {
+            assert!(is_geojson(&from_str(geojson).unwrap()), "{}", geojson);
+        }
+        for geojson in [
+            r#"{"type":"GeometryCollection","geometries":[{"type":"Point"}]}"#,
+            r#"{"type":"Circle","coordinates":[0,0]}"#,
+            r#"{"type":"FeatureCollection","features":[{"type":"Point","coordinates":[0,0]}]}"#,
+        ] {
+            assert!(!is_geojson(&from_str(geojson).unwrap()), "{}", geojson);
+        }
+    }
+
+    #[test]
+    fn test_process_bare_geometries() {
+        let options = MinifyOptions::builder().decimal(1).build();
+
+        let mut polygon: SonicValue =
+            from_str(r#"{"type":"Polygon","coordinates":[[[0.12,0.12],[1.12,0.12],[1.12,1.12],[0.12,0.12]]]}"#)
+                .unwrap();
+        process_geojson(&mut polygon, &options).unwrap();
+        assert_eq!(
+            polygon,
+            from_str::<SonicValue>(
+                r#"{"type":"Polygon","coordinates":[[[0.1,0.1],[1.1,0.1],[1.1,1.1],[0.1,0.1]]]}"#
+            )
+            .unwrap()
+        );
+
+        let mut collection: SonicValue = from_str(
+            r#"{"type":"Feature","properties":{},"geometry":{"type":"GeometryCollection","geometries":[{"type":"Point","coordinates":[0.12,0.12]},{"type":"GeometryCollection","geometries":[{"type":"LineString","coordinates":[[2.26,2.26],[3.33,3.33]]}]}]}}"#,
+        )
+        .unwrap();
+        process_geojson(&mut collection, &options).unwrap();
+        let geometries = &collection["geometry"]["geometries"];
+        assert_eq!(
+            geometries[0]["coordinates"],
+            from_str::<SonicValue>("[0.1,0.1]").unwrap()
+        );
+        assert_eq!(
+            geometries[1]["geometries"][0]["coordinates"],
+            from_str::<SonicValue>("[[2.3,2.3],[3.3,3.3]]").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_process_typeless_and_unknown_documents() {
+        let options = MinifyOptions::builder().decimal(1).build();
+
+        let mut typeless: SonicValue = from_str(
+            r#"{"features":[{"type":"Feature","properties":{},"geometry":{"type":"Point","coordinates":[0.12,0.12]}}]}"#,
+        )
+        .unwrap();
+        process_geojson(&mut typeless, &options).unwrap();
+        assert_eq!(
+            typeless["features"][0]["geometry"]["coordinates"],
+            from_str::<SonicValue>("[0.1,0.1]").unwrap()
+        );
+
+        let mut unknown: SonicValue = from_str(r#"{"type":"Circle","radius":1}"#).unwrap();
+        assert!(matches!(
+            process_geojson(&mut unknown, &options),
+            Err(MyError::InvalidGeoJson(_))
+        ));
+    }
+
+    #[test]
+    fn test_compute_and_strip_collection_bbox() {
+        let mut parsed_json = read_json_file("data/small-departements.geojson").unwrap();
+
+        process_geojson(
+            &mut parsed_json,
+            &MinifyOptions::builder().bbox(BboxMode::Compute).build(),
+        )
+        .unwrap();
+        let bbox = parsed_json["bbox"].as_array().unwrap();
+        assert_eq!(bbox.len(), 4);
+        for feature in parsed_json["features"].as_array().unwrap().iter() {
+            let feature_bbox = feature["bbox"].as_array().unwrap();
+            assert!(bbox[0].as_f64() <= feature_bbox[0].as_f64());
+            assert!(bbox[3].as_f64() >= feature_bbox[3].as_f64());
+        }
+
+        process_geojson(
+            &mut parsed_json,
+            &MinifyOptions::builder().bbox(BboxMode::Strip).build(),
+        )
+        .unwrap();
+        assert!(parsed_json.get("bbox").is_none());
+        assert!(parsed_json["features"][0].get("bbox").is_none());
+    }
+
+    #[test]
+    fn test_unsupported_crs_member_needs_from_crs() {
+        let geojson = r#"{
+            "type": "FeatureCollection",
+            "crs": {"type": "name", "properties": {"name": "urn:ogc:def:crs:EPSG::27572"}},
+            "features": [{
+                "type": "Feature",
+                "properties": {},
+                "geometry": {"type": "Point", "coordinates": [600000.5, 2400000.25]}
+            }]
+        }"#;
+
+        let mut parsed_json: SonicValue = from_str(geojson).unwrap();
+        assert!(matches!(
+            process_geojson(&mut parsed_json, &MinifyOptions::builder().build()),
+            Err(MyError::UnsupportedCrs(_))
+        ));
+
+        let mut parsed_json: SonicValue = from_str(geojson).unwrap();
+        let options = MinifyOptions::builder().from_crs(Crs::Wgs84).build();
+        process_geojson(&mut parsed_json, &options).unwrap();
+        assert!(parsed_json.get("crs").is_none());
+    }
+
+    #[test]
+    fn test_reproject_follows_the_crs_member() {
+        let mut parsed_json: SonicValue = from_str(
+            r#"{
+                "type": "FeatureCollection",
+                "crs": {"type": "name", "properties": {"name": "urn:ogc:def:crs:EPSG::2154"}},
+                "bbox": [700000.0, 6600000.0, 700000.0, 6600000.0],
+                "features": [{
+                    "type": "Feature",
+                    "properties": {},
+                    "geometry": {"type": "Point", "coordinates": [700000.0, 6600000.0]}
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        process_geojson(
+            &mut parsed_json,
+            &MinifyOptions::builder().decimal(6).build(),
+        )
+        .unwrap();
+        assert!(parsed_json.get("crs").is_none());
+        let point = &parsed_json["features"][0]["geometry"]["coordinates"];
+        assert_eq!(point[0].as_f64(), Some(3.0));
+        assert_eq!(point[1].as_f64(), Some(46.5));
+        assert_eq!(parsed_json["bbox"][3].as_f64(), Some(46.5));
+
+        process_geojson(
+            &mut parsed_json,
+            &MinifyOptions::builder()
+                .to_crs(Crs::WebMercator)
+                .decimal(0)
+                .build(),
+        )
+        .unwrap();
+        assert_eq!(
+            Crs::from_crs_member(&parsed_json["crs"]),
+            Some(Crs::WebMercator)
+        );
+        let point = &parsed_json["features"][0]["geometry"]["coordinates"];
+        assert_eq!(point[0].as_f64(), Some(333_958.0));
+    }
+
+    #[test]
+    fn test_remove_features_below_min_area() {
+        let mut parsed_json = read_json_file("data/small-departements.geojson").unwrap();
+        let count = parsed_json["features"].as_array().unwrap().len();
+        let areas: Vec<f64> = parsed_json["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|feature| geometry_area(&feature["geometry"], true))
+            .collect();
+        let median = {
+            let mut sorted = areas.clone();
+            sorted.sort_by(f64::total_cmp);
+            sorted[sorted.len() / 2]
+        };
+
+        process_geojson(
+            &mut parsed_json,
+            &MinifyOptions::builder().min_feature_area(median).build(),
+        )
+        .unwrap();
+
+        let kept = parsed_json["features"].as_array().unwrap().len();
+        assert_eq!(kept, areas.iter().filter(|area| **area >= median).count());
+        assert!(kept < count);
+
+        let mut feature: SonicValue = from_str(
+            r#"{"type":"Feature","properties":{},"geometry":{"type":"Polygon","coordinates":[[[0,0],[0.001,0],[0,0.001],[0,0]]]}}"#,
+        )
+        .unwrap();
+        process_geojson(
+            &mut feature,
+            &MinifyOptions::builder().min_feature_area(1e6).build(),
+        )
+        .unwrap();
+        assert_eq!(feature["type"].as_str(), Some("FeatureCollection"));
+    }
+
+    #[test]
+    fn test_clip_to_a_departement_mask() {
+        let mut parsed_json = read_json_file("data/departements.geojson").unwrap();
+        let morbihan = parsed_json["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|feature| feature["properties"]["nom"].as_str() == Some("Morbihan"))
+            .unwrap()
+            .clone();
+        let mask = Mask::from_geojson(&morbihan).unwrap();
+
+        process_geojson(
+            &mut parsed_json,
+            &MinifyOptions::builder().clip(mask).build(),
+        )
+        .unwrap();
+
+        // The neighbours only touch the mask, they are removed
+        let features = parsed_json["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        let area = |feature: &SonicValue| geometry_area(&feature["geometry"], true).unwrap();
+        assert!((area(&features[0]) - area(&morbihan)).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_filter_features_by_location() {
+        let parsed_json = read_json_file("data/departements.geojson").unwrap();
+        let bretagne: SonicValue = parsed_json["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|feature| {
+                ["22", "29", "35", "56"].contains(&feature["properties"]["code"].as_str().unwrap())
+            })
+            .cloned()
+            .collect::<sonic_rs::Array>()
+            .into_value();
+        let mut mask = Object::new();
+        mask.insert("type", "FeatureCollection");
+        mask.insert("features", bretagne);
+        let mask = Arc::new(Mask::from_geojson(&mask.into_value()).unwrap());
+        let filtered = |filter: SpatialFilter| {
+            let mut geojson = parsed_json.clone();
+            process_geojson(
+                &mut geojson,
+                &MinifyOptions::builder().spatial_filter(filter).build(),
+            )
+            .unwrap();
+            geojson["features"].as_array().unwrap().len()
+        };
+
+        // The neighbours of the region only touch it
+        assert_eq!(filtered(SpatialFilter::Within(mask.clone())), 4);
+        assert_eq!(filtered(SpatialFilter::Intersects(mask.clone())), 4);
+        let bbox_matches = filtered(SpatialFilter::Bbox(mask.bbox()));
+        assert!(bbox_matches > 4);
+
+        // The geometries are not clipped
+        let mut geojson = parsed_json.clone();
+        process_geojson(
+            &mut geojson,
+            &MinifyOptions::builder()
+                .spatial_filter(SpatialFilter::Bbox(mask.bbox()))
+                .build(),
+        )
+        .unwrap();
+        let original = parsed_json["features"].as_array().unwrap();
+        for feature in geojson["features"].as_array().unwrap().iter() {
+            assert!(original.iter().any(|o| o == feature));
+        }
+    }
+
+    #[test]
+    fn test_recompute_the_kept_bbox_of_filtered_collections() {
+        let input = r#"{"type":"FeatureCollection","bbox":[0,0,10,10],"features":[
+            {"type":"Feature","properties":{},"geometry":{"type":"Point","coordinates":[1,1]}},
+            {"type":"Feature","properties":{},"geometry":{"type":"Point","coordinates":[10,10]}}
+        ]}"#;
+        let filtered = |options: MinifyOptions| {
+            let mut geojson: SonicValue = from_str(input).unwrap();
+            process_geojson(&mut geojson, &options).unwrap();
+            geojson["bbox"].clone()
+        };
+
+        let within = Arc::new(Mask::from_bbox([0.0, 0.0, 2.0, 2.0]));
+        assert_eq!(
+            filtered(
+                MinifyOptions::builder()
+                    .spatial_filter(SpatialFilter::Within(within))
+                    .build()
+            ),
+            sonic_rs::json!([1.0, 1.0, 1.0, 1.0])
+        );
+        assert_eq!(
+            filtered(MinifyOptions::default()),
+            sonic_rs::json!([0, 0, 10, 10])
+        );
+    }
+
+    #[test]
+    fn test_filter_the_input_geometry_of_each_feature() {
+        let input = r#"{"type":"FeatureCollection","features":[
+            {"type":"Feature","properties":{"id":1},"geometry":{"type":"LineString","coordinates":[[1,1],[1.5,1.5]]}},
+            {"type":"Feature","properties":{"id":2},"geometry":{"type":"LineString","coordinates":[[1,1],[3,3]]}}
+        ]}"#;
+        let kept = |options: MinifyOptions| {
+            let mut geojson: SonicValue = from_str(input).unwrap();
+            process_geojson(&mut geojson, &options).unwrap();
+            geojson["features"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|feature| feature["properties"]["id"].as_u64().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // The second line is clipped inside the mask, but it was not within it
+        let mask = [0.0, 0.0, 2.0, 2.0];
+        let options = MinifyOptions::builder()
+            .clip(Mask::from_bbox(mask))
+            .spatial_filter(SpatialFilter::Within(Arc::new(Mask::from_bbox(mask))))
+            .jobs(2)
+            .build();
+        assert_eq!(kept(options), vec![1]);
+
+        // The filter is in the output CRS, the input in Web Mercator
+        let mut geojson: SonicValue = from_str(input).unwrap();
+        let options = MinifyOptions::builder().to_crs(Crs::WebMercator).build();
+        process_geojson(&mut geojson, &options).unwrap();
+        let options = MinifyOptions::builder()
+            .spatial_filter(SpatialFilter::Bbox([2.5, 2.5, 4.0, 4.0]))
+            .build();
+        process_geojson(&mut geojson, &options).unwrap();
+        assert_eq!(geojson["features"].as_array().unwrap().len(), 1);
+        assert_eq!(geojson["features"][0]["properties"]["id"].as_u64(), Some(2));
+    }
+
+    #[test]
+    fn test_read_topojson_file_as_geojson() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let geojson = read_json_file("data/small-departements.geojson").unwrap();
+        write_topojson_file(&geojson, file.reopen().unwrap(), false, "departements", 0).unwrap();
+
+        let parsed_json = read_json_file(file.path()).unwrap();
+
+        assert_eq!(parsed_json["type"].as_str(), Some("FeatureCollection"));
+        assert_eq!(
+            parsed_json["features"][1]["properties"],
+            geojson["features"][1]["properties"]
+        );
+        assert!(is_geojson(&parsed_json["features"][1]));
+    }
+
+    #[test]
+    fn test_stream_processing_matches_in_memory_processing() {
+        let output = tempfile::NamedTempFile::new().unwrap();
+        let args = Args::parse_from([
+            "mini-geojson",
+            "-i",
+            "data/small-departements.geojson",
+            "-r",
+            "name,key_to_delete",
+            "--stream",
+        ]);
+
+        let sizes =
+            handle_geojson_processing(&args, &args.input[0], &output.path().to_path_buf()).unwrap();
+
+        assert_eq!(
+            sizes.minified_size,
+            fs::metadata(output.path()).unwrap().len()
+        );
+
+        let streamed = read_json_file(output.path()).unwrap();
+        let expected =
+            read_json_file("data/min_small-departements-keys-to-delete.geojson").unwrap();
+        assert_eq!(streamed, expected);
+    }
+
+    fn stream(input: &str, options: &[&str]) -> Result<SonicValue, MyError> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(input.as_bytes()).unwrap();
+        let output = tempfile::NamedTempFile::new().unwrap();
+        let input_path = file.path().to_str().unwrap();
+        let args = Args::parse_from(
+            ["mini-geojson", "-i", input_path, "--stream"]
+                .iter()
+                .chain(options),
+        );
+        handle_geojson_stream_processing(&args, input_path, &output.path().to_path_buf())?;
+        read_json_file(output.path())
+    }
+
+    #[test]
+    fn test_stream_rejects_what_it_cannot_process() {
+        for input in [
+            r#"{"type":"Feature","properties":{},"geometry":{"type":"Point","coordinates":[1.234,5.678]}}"#,
+            r#"{"type":"Point","coordinates":[1.234,5.678]}"#,
+            r#"{"type":"Topology","objects":{},"arcs":[]}"#,
+            r#"{"features":[]}"#,
+            r#"{"type":"FeatureCollection","crs":{"type":"name","properties":{"name":"urn:ogc:def:crs:EPSG::2154"}},"features":[]}"#,
+            r#"{"type":"FeatureCollection","crs":{"type":"name","properties":{"name":"urn:ogc:def:crs:EPSG::27572"}},"features":[]}"#,
+        ] {
+            assert!(
+                matches!(
+                    stream(input, &["--fix"]),
+                    Err(MyError::StreamingNotSupported(_))
+                ),
+                "{}",
+                input
+            );
+        }
+
+        let wgs84 = r#"{"type":"FeatureCollection","crs":{"type":"name","properties":{"name":"urn:ogc:def:crs:OGC:1.3:CRS84"}},"features":[]}"#;
+        assert!(stream(wgs84, &[]).is_ok());
+    }
+
+    #[test]
+    fn test_stream_removes_the_bbox_when_features_may_change() {
+        let input = r#"{"type":"FeatureCollection","bbox":[0,0,10,10],"features":[
+            {"type":"Feature","properties":{},"geometry":{"type":"Polygon","coordinates":[[[0,0],[0.0001,0],[0.0001,0.0001],[0,0]]]}},
+            {"type":"Feature","properties":{},"geometry":{"type":"Point","coordinates":[10,10]}}
+        ]}"#;
+
+        let streamed = stream(input, &["--min-feature-area", "1000000"]).unwrap();
+
+        assert!(streamed.get("bbox").is_none());
+        assert_eq!(streamed["features"].as_array().unwrap().len(), 1);
+
+        let streamed = stream(input, &["--clip-bbox", "0,0,2,2"]).unwrap();
+
+        assert!(streamed.get("bbox").is_none());
+        assert_eq!(streamed["features"].as_array().unwrap().len(), 1);
+
+        let streamed = stream(input, &["--bbox-filter", "5,5,10,10"]).unwrap();
+
+        assert!(streamed.get("bbox").is_none());
+        assert_eq!(streamed["features"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_stream_removes_the_output_of_a_failed_stream() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(br#"{"features":[{"type":"Feature","properties":{},"geometry":null}],"type":"Feature"}"#)
+            .unwrap();
+        let output = tempfile::NamedTempFile::new().unwrap();
+        let input_path = file.path().to_str().unwrap();
+        let args = Args::parse_from(["mini-geojson", "-i", input_path, "--stream"]);
+
+        let streamed =
+            handle_geojson_stream_processing(&args, input_path, &output.path().to_path_buf());
+
+        assert!(matches!(streamed, Err(MyError::StreamingNotSupported(_))));
+        assert!(!output.path().exists());
+    }
+
+    #[test]
+    fn test_stream_rounds_the_kept_bbox() {
+        let input = r#"{"type":"FeatureCollection","bbox":[1.23456,5.67891,2.34567,6.78912],"features":[
+            {"type":"Feature","properties":{},"geometry":{"type":"Point","coordinates":[1.23456,5.67891]}}
+        ]}"#;
+
+        let streamed = stream(input, &["-d", "2", "-R"]).unwrap();
+
+        assert_eq!(streamed["bbox"], sonic_rs::json!([1.23, 5.68, 2.35, 6.79]));
+        assert_eq!(
+            streamed["features"][0]["geometry"]["coordinates"],
+            sonic_rs::json!([1.23, 5.68])
+        );
+    }
+
     #[test]
     fn test_is_geosjon_coordinates_truncated_by_three() {
         let file_path = "data/test-geojson-true.geojson";
         let mut parsed_json = read_json_file(file_path).unwrap();
-        process_geojson(&mut parsed_json, Some(3), false, None, None).unwrap();
+        process_geojson(
+            &mut parsed_json,
+            &MinifyOptions::builder().decimal(3).build(),
+        )
+        .unwrap();
         let expected = read_json_file("data/test-geojson-true-truncated.geojson").unwrap();
         assert_eq!(parsed_json, expected);
     }
@@ -386,10 +1740,26 @@ mod tests {
         let mut parsed_json = read_json_file(file_path).unwrap();
         process_geojson(
             &mut parsed_json,
-            None,
-            false,
-            Some(&vec!["name".to_string(), "key_to_delete".to_string()]),
-            None,
+            &MinifyOptions::builder()
+                .properties_to_remove(["name", "key_to_delete"])
+                .build(),
+        )
+        .unwrap();
+        let expected =
+            read_json_file("data/min_small-departements-keys-to-delete.geojson").unwrap();
+        assert_eq!(parsed_json, expected);
+    }
+
+    #[test]
+    fn test_parallel_processing_keeps_feature_order() {
+        let file_path = "data/small-departements.geojson";
+        let mut parsed_json = read_json_file(file_path).unwrap();
+        process_geojson(
+            &mut parsed_json,
+            &MinifyOptions::builder()
+                .properties_to_remove(["name", "key_to_delete"])
+                .jobs(4)
+                .build(),
         )
         .unwrap();
         let expected =
@@ -403,10 +1773,10 @@ mod tests {
         let mut parsed_json = read_json_file(file_path).unwrap();
         process_geojson(
             &mut parsed_json,
-            None,
-            true,
-            Some(&vec!["name".to_string(), "key_to_delete".to_string()]),
-            None,
+            &MinifyOptions::builder()
+                .remove_null_properties(true)
+                .properties_to_remove(["name", "key_to_delete"])
+                .build(),
         )
         .unwrap();
         let expected =
@@ -421,16 +1791,47 @@ mod tests {
         let mut parsed_json = read_json_file(file_path).unwrap();
         process_geojson(
             &mut parsed_json,
-            None,
-            false,
-            None,
-            Some(&vec!["id".to_string(), "name".to_string(), "key_to_keep".to_string()]),
+            &MinifyOptions::builder()
+                .properties_to_keep(["id", "name", "key_to_keep"])
+                .build(),
         )
         .unwrap();
         let expected = read_json_file("data/min_small-departements-keys-to-keep.geojson").unwrap();
         assert_eq!(parsed_json, expected);
     }
 
+    #[test]
+    fn test_simplify_with_preserved_topology_keeps_shared_borders() {
+        let file_path = "data/small-departements.geojson";
+        let mut parsed_json = read_json_file(file_path).unwrap();
+        let simplification = Simplification::DouglasPeucker {
+            tolerance: 0.01,
+            unit: ToleranceUnit::Degrees,
+        };
+        // The clip runs before the simplification, which sees its cuts
+        let mut bbox = collection_bbox(&parsed_json).unwrap();
+        bbox[0] += 0.01;
+        process_geojson(
+            &mut parsed_json,
+            &MinifyOptions::builder()
+                .simplification(simplification)
+                .preserve_topology(true)
+                .clip(Mask::from_bbox(bbox))
+                .build(),
+        )
+        .unwrap();
+
+        let first = &parsed_json["features"][0]["geometry"];
+        let second = &parsed_json["features"][1]["geometry"];
+        let mut lines = geometry_lines(first);
+        lines.extend(geometry_lines(second));
+        let topology = build_topology(&lines);
+        let first_arcs: HashSet<i64> = topology.lines[0].iter().map(|r| (*r).max(!*r)).collect();
+        assert!(topology.lines[1]
+            .iter()
+            .any(|r| first_arcs.contains(&(*r).max(!*r))));
+    }
+
     //#[test]
     // fn test_no_whitespace_no_new_line() -> Result<(), io::Error> {
     //     let geojson = json!({
@@ -1,5 +1,222 @@
+use crate::minify::MinifyOptions;
+use crate::pipeline::Pipeline;
+use crate::projection::{Crs, Reprojection};
+use clap::ValueEnum;
 use log::{debug, info};
-use sonic_rs::{JsonValueMutTrait, JsonValueTrait, Value};
+use sonic_rs::{Array, JsonContainerTrait, JsonValueMutTrait, JsonValueTrait, Value};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Mean Earth radius in meters, used to project degrees to meters.
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Unit in which a simplification tolerance is expressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ToleranceUnit {
+    Degrees,
+    Meters,
+}
+
+/// Line simplification applied to LineStrings, polygon rings and their Multi* variants.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Simplification {
+    /// Ramer-Douglas-Peucker: drop the vertices closer than `tolerance` to the simplified line.
+    DouglasPeucker { tolerance: f64, unit: ToleranceUnit },
+    /// Visvalingam-Whyatt: drop the vertices whose effective area is below the threshold.
+    Visvalingam {
+        threshold: VisvalingamThreshold,
+        unit: ToleranceUnit,
+    },
+}
+
+impl Simplification {
+    /// The same simplification for coordinates in `crs`: projected coordinates are
+    /// already in meters, so a tolerance in meters needs no conversion from degrees.
+    pub fn for_crs(self, crs: Crs) -> Self {
+        if crs.is_geographic() {
+            return self;
+        }
+        match self {
+            Simplification::DouglasPeucker { tolerance, .. } => Simplification::DouglasPeucker {
+                tolerance,
+                unit: ToleranceUnit::Degrees,
+            },
+            Simplification::Visvalingam { threshold, .. } => Simplification::Visvalingam {
+                threshold,
+                unit: ToleranceUnit::Degrees,
+            },
+        }
+    }
+}
+
+/// How Visvalingam-Whyatt decides which vertices to drop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisvalingamThreshold {
+    /// Minimum effective area, in square degrees or square meters.
+    Area(f64),
+    /// Percentage of the vertices of each line and ring to keep (mapshaper style).
+    KeepPercentage(f64),
+}
+
+/// Algorithm used by the `--simplify` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SimplifyAlgorithm {
+    DouglasPeucker,
+    Visvalingam,
+}
+
+/// How the coordinates are rounded, instead of a fixed number of decimals.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    /// A ground resolution in meters: the decimals follow the unit of the CRS
+    /// and, for longitudes, the latitude of each position.
+    Meters(f64),
+    /// A number of significant digits in each coordinate, suited to projected coordinates.
+    SignificantDigits(u32),
+}
+
+/// Beyond this, decimals are below the precision of an f64 coordinate.
+pub const MAX_DECIMALS: usize = 15;
+
+/// Length of a degree of latitude, or of longitude at the equator, in meters.
+const METERS_PER_DEGREE: f64 = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+
+/// Number of decimals so that rounding moves a coordinate by at most `resolution`,
+/// expressed in the unit of the coordinate.
+pub fn decimals_for_resolution(resolution: f64) -> usize {
+    let decimals = (0.5 / resolution).log10().ceil();
+    if decimals.is_nan() {
+        return 0;
+    }
+    decimals.clamp(0.0, MAX_DECIMALS as f64) as usize
+}
+
+/// Round a number to `digits` significant digits.
+pub fn round_significant(number: f64, digits: u32) -> f64 {
+    if number == 0.0 || !number.is_finite() {
+        return number;
+    }
+    let decimals = digits as i32 - (number.abs().log10().floor() as i32 + 1);
+    if decimals >= 0 {
+        truncate_coord(number, (decimals as usize).min(MAX_DECIMALS))
+    } else {
+        let multiplier = 10f64.powi(-decimals);
+        (number / multiplier).round() * multiplier
+    }
+}
+
+/// How the ordinates of each position are rounded, and which ones are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rounding {
+    /// Decimals of the coordinates without a precision of their own.
+    pub decimal: Option<usize>,
+    /// Ground resolution or significant digits, instead of `decimal`.
+    pub precision: Option<Precision>,
+    /// Decimals of the elevation, the third ordinate.
+    pub z_decimal: Option<usize>,
+    /// Decimals of the measure, the fourth ordinate.
+    pub m_decimal: Option<usize>,
+    /// Drop every ordinate after the longitude and latitude.
+    pub force_2d: bool,
+    /// Whether the coordinates are longitudes and latitudes or projected meters.
+    pub geographic: bool,
+}
+
+impl Rounding {
+    fn round(&self, index: usize, number: f64, latitude: f64) -> Option<f64> {
+        let own_decimal = match index {
+            2 => self.z_decimal,
+            3 => self.m_decimal,
+            _ => None,
+        };
+        if let Some(decimal) = own_decimal {
+            return Some(truncate_coord(number, decimal));
+        }
+        match self.precision {
+            Some(Precision::SignificantDigits(digits)) => Some(round_significant(number, digits)),
+            Some(Precision::Meters(meters)) => {
+                let resolution = match index {
+                    0 if self.geographic => {
+                        meters / (METERS_PER_DEGREE * latitude.to_radians().cos())
+                    }
+                    1 if self.geographic => meters / METERS_PER_DEGREE,
+                    // Projected coordinates and elevations are in meters
+                    _ => meters,
+                };
+                Some(truncate_coord(number, decimals_for_resolution(resolution)))
+            }
+            None => self.decimal.map(|decimal| truncate_coord(number, decimal)),
+        }
+    }
+}
+
+fn round_position(position: &mut Array, rounding: &Rounding) {
+    if rounding.force_2d {
+        position.truncate(2);
+    }
+    let latitude = position
+        .get(1)
+        .and_then(|latitude| latitude.as_f64())
+        .unwrap_or_default();
+    for (index, number) in position.iter_mut().enumerate() {
+        if let Some(rounded) = number
+            .as_f64()
+            .and_then(|value| rounding.round(index, value, latitude))
+        {
+            *number = Value::new_f64(rounded).unwrap_or_default();
+        }
+    }
+}
+
+/// Round the coordinates of a geometry, including the members of GeometryCollections.
+/// With `force_2d`, 3D bboxes are reduced to their horizontal extent.
+pub fn round_geometry(geometry: &mut Value, rounding: &Rounding) {
+    if let Some(coordinates) = geometry.get_mut("coordinates") {
+        for_each_position(coordinates, &mut |position| {
+            round_position(position, rounding)
+        });
+    }
+    if rounding.force_2d {
+        bbox_to_2d(geometry);
+    }
+    for_each_geometry(geometry, |member| round_geometry(member, rounding));
+}
+
+/// Reduce a 3D bbox ([west, south, bottom, east, north, top]) to [west, south, east, north].
+pub fn bbox_to_2d(object: &mut Value) {
+    let Some(bbox) = object.get_mut("bbox").and_then(|b| b.as_array_mut()) else {
+        return;
+    };
+    if bbox.len() == 6 {
+        bbox.remove(5);
+        bbox.remove(2);
+    }
+}
+
+/// Round a bbox ([west, south, east, north] or [west, south, bottom, east, north, top])
+/// like the positions it bounds.
+pub fn round_bbox(bbox: &mut Value, rounding: &Rounding) {
+    let Some(edges) = bbox.as_array_mut() else {
+        return;
+    };
+    let dimensions = (edges.len() / 2).max(1);
+    // A ground resolution needs the most decimals at the latitude closest to a pole
+    let latitude = [1, dimensions + 1]
+        .iter()
+        .filter_map(|&index| edges.get(index).and_then(|edge| edge.as_f64()))
+        .map(f64::abs)
+        .fold(0.0, f64::max);
+    for (index, edge) in edges.iter_mut().enumerate() {
+        if let Some(rounded) = edge
+            .as_f64()
+            .and_then(|value| rounding.round(index % dimensions, value, latitude))
+        {
+            *edge = Value::new_f64(rounded).unwrap_or_default();
+        }
+    }
+}
 
 pub fn truncate_coordinate_in_array(coordinates: &mut Value, decimal: usize) {
     info!(
@@ -7,65 +224,1315 @@ pub fn truncate_coordinate_in_array(coordinates: &mut Value, decimal: usize) {
         decimal
     );
 
-    fn truncate_recursive(coord: &mut Value, decimal: usize) {
-        debug!("Truncating coordinate: {:?}", coord);
-        if coord.is_array() {
-            if let Some(coords_array) = coord.as_array_mut() {
-                for c in coords_array {
-                    truncate_recursive(c, decimal);
-                }
-            }
-        } else if coord.is_number() {
-            if let Some(number) = coord.as_f64() {
-                let truncated_number = truncate_coord(number, decimal);
+    for_each_position(coordinates, &mut |position| {
+        debug!("Truncating coordinate: {:?}", position);
+        for number in position.iter_mut() {
+            if let Some(original) = number.as_f64() {
+                let truncated_number = truncate_coord(original, decimal);
                 debug!(
                     "Original number: {}, Truncated number: {}",
-                    number, truncated_number
+                    original, truncated_number
                 );
-                *coord = truncated_number.try_into().unwrap()
+                *number = Value::new_f64(truncated_number).unwrap_or_default();
             }
         }
-    }
-    truncate_recursive(coordinates, decimal);
+    });
     info!("Coordinate truncation completed");
 }
 
+/// Run `f` on every position of a coordinates array, whatever its nesting:
+/// a position is an array starting with a number.
+pub(crate) fn for_each_position<F: FnMut(&mut Array)>(coordinates: &mut Value, f: &mut F) {
+    let Some(array) = coordinates.as_array_mut() else {
+        return;
+    };
+    if array.first().is_some_and(|first| first.is_number()) {
+        f(array);
+    } else {
+        for nested in array.iter_mut() {
+            for_each_position(nested, f);
+        }
+    }
+}
+
+/// Truncate the coordinates of a geometry, including the members of GeometryCollections.
+pub fn truncate_geometry(geometry: &mut Value, decimal: usize) {
+    if let Some(coordinates) = geometry.get_mut("coordinates") {
+        truncate_coordinate_in_array(coordinates, decimal);
+    }
+    for_each_geometry(geometry, |member| truncate_geometry(member, decimal));
+}
+
+/// Round to `decimal` decimals, at most [`MAX_DECIMALS`].
 pub fn truncate_coord(coord: f64, decimal: usize) -> f64 {
-    let multiplier = 10u64.pow(decimal as u32) as f64;
+    let multiplier = 10u64.pow(decimal.min(MAX_DECIMALS) as u32) as f64;
     (coord * multiplier).round() / multiplier
 }
 
-pub fn process_feature(
-    feature: &mut Value,
-    decimal: Option<usize>,
-    remove_null_properties: bool,
-    properties_to_remove: Option<&Vec<String>>,
-    properties_to_keep: Option<&Vec<String>>,
-) {
-    if let Some(geometry) = feature.get_mut("geometry").and_then(|g| g.as_object_mut()) {
-        if let Some(coords) = geometry.get_mut(&"coordinates".to_string()) {
-            if let Some(decimal_value) = decimal {
-                truncate_coordinate_in_array(coords, decimal_value);
+/// Convert the coordinates of a geometry, including the members of GeometryCollections,
+/// see [`Reprojection`]. Elevations and measures are kept as they are.
+pub fn reproject_geometry(geometry: &mut Value, reprojection: &Reprojection) {
+    if let Some(coordinates) = geometry.get_mut("coordinates") {
+        for_each_position(coordinates, &mut |position| {
+            if let (Some(x), Some(y)) = (
+                position[0].as_f64(),
+                position.get(1).and_then(|y| y.as_f64()),
+            ) {
+                let (x, y) = reprojection.convert(x, y);
+                position[0] = Value::new_f64(x).unwrap_or_default();
+                position[1] = Value::new_f64(y).unwrap_or_default();
+            }
+        });
+    }
+    for_each_geometry(geometry, |member| reproject_geometry(member, reprojection));
+}
+
+/// Cut the LineStrings and Polygons crossing the antimeridian into MultiLineStrings and
+/// MultiPolygons whose parts stay within [-180, 180], as RFC 7946 section 3.1.9 requires.
+/// The cut points are interpolated linearly, elevations included.
+/// Returns the number of geometries that were cut.
+pub fn split_antimeridian(geometry: &mut Value) -> usize {
+    let geometry_type = geometry
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .to_string();
+    if geometry_type == "GeometryCollection" {
+        let mut split = 0;
+        for_each_geometry(geometry, |member| split += split_antimeridian(member));
+        return split;
+    }
+    let Some(coordinates) = geometry.get("coordinates") else {
+        return 0;
+    };
+    let (parts, multi_type) = match geometry_type.as_str() {
+        "LineString" => (
+            positions(coordinates).and_then(|line| split_line(&line)),
+            "MultiLineString",
+        ),
+        "MultiLineString" => (
+            split_each(coordinates, |line| split_line(&positions(line)?)),
+            "MultiLineString",
+        ),
+        "Polygon" => (split_polygon(coordinates), "MultiPolygon"),
+        "MultiPolygon" => (split_each(coordinates, split_polygon), "MultiPolygon"),
+        _ => (None, ""),
+    };
+    let Some(parts) = parts else {
+        return 0;
+    };
+    debug!(
+        "Cut {} into {} parts at the antimeridian",
+        geometry_type,
+        parts.len()
+    );
+    if let Some(object) = geometry.as_object_mut() {
+        let single = parts.len() == 1 && !geometry_type.starts_with("Multi");
+        let coordinates = match single {
+            true => parts.into_iter().next().unwrap_or_default(),
+            false => parts.into_iter().collect::<Array>().into_value(),
+        };
+        if !single {
+            object.insert("type", multi_type);
+        }
+        object.insert("coordinates", coordinates);
+    }
+    1
+}
+
+/// Split each part of a Multi* geometry, `None` when none of them is cut.
+fn split_each<F>(parts: &Value, mut split: F) -> Option<Vec<Value>>
+where
+    F: FnMut(&Value) -> Option<Vec<Value>>,
+{
+    let mut cut = false;
+    let mut result = Vec::new();
+    for part in parts.as_array()?.iter() {
+        match split(part) {
+            Some(pieces) => {
+                cut = true;
+                result.extend(pieces);
+            }
+            None => result.push(part.clone()),
+        }
+    }
+    cut.then_some(result)
+}
+
+/// The ordinates of every position of a part, `None` if one of them is not a position.
+pub(crate) fn positions(part: &Value) -> Option<Vec<Vec<f64>>> {
+    part.as_array()?
+        .iter()
+        .map(|position| {
+            let ordinates: Vec<f64> = position
+                .as_array()?
+                .iter()
+                .map(|number| number.as_f64())
+                .collect::<Option<_>>()?;
+            (ordinates.len() >= 2).then_some(ordinates)
+        })
+        .collect()
+}
+
+pub(crate) fn positions_to_value(positions: &[Vec<f64>]) -> Value {
+    positions
+        .iter()
+        .map(|position| {
+            position
+                .iter()
+                .map(|number| Value::new_f64(*number).unwrap_or_default())
+                .collect::<Array>()
+                .into_value()
+        })
+        .collect::<Array>()
+        .into_value()
+}
+
+/// The position between `a` and `b` whose longitude is `longitude`.
+fn interpolate_at_longitude(a: &[f64], b: &[f64], longitude: f64) -> Vec<f64> {
+    let t = (longitude - a[0]) / (b[0] - a[0]);
+    let mut position: Vec<f64> = a.iter().zip(b).map(|(a, b)| a + (b - a) * t).collect();
+    position[0] = longitude;
+    position
+}
+
+fn push_distinct(positions: &mut Vec<Vec<f64>>, position: Vec<f64>) {
+    if positions.last() != Some(&position) {
+        positions.push(position);
+    }
+}
+
+/// The pieces of a line cut where it jumps by more than 180° of longitude,
+/// `None` when it does not cross the antimeridian.
+fn split_line(line: &[Vec<f64>]) -> Option<Vec<Value>> {
+    let first = line.first()?;
+    let mut pieces = vec![vec![first.clone()]];
+    for pair in line.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        if (b[0] - a[0]).abs() > 180.0 {
+            let edge = if a[0] > 0.0 { 180.0 } else { -180.0 };
+            let mut unwrapped = b.clone();
+            unwrapped[0] += 2.0 * edge;
+            let mut crossing = interpolate_at_longitude(a, &unwrapped, edge);
+            if let Some(piece) = pieces.last_mut() {
+                push_distinct(piece, crossing.clone());
+            }
+            crossing[0] = -edge;
+            pieces.push(vec![crossing]);
+        }
+        if let Some(piece) = pieces.last_mut() {
+            push_distinct(piece, b.clone());
+        }
+    }
+    if pieces.len() == 1 {
+        return None;
+    }
+    Some(
+        pieces
+            .iter()
+            .filter(|piece| piece.len() >= 2)
+            .map(|piece| positions_to_value(piece))
+            .collect(),
+    )
+}
+
+/// Make the longitudes of a ring continuous: no jump of more than 180° between two positions.
+fn unwrap_longitudes(ring: &mut [Vec<f64>]) {
+    for index in 1..ring.len() {
+        let previous = ring[index - 1][0];
+        let longitude = &mut ring[index][0];
+        *longitude += 360.0 * ((previous - *longitude) / 360.0).round();
+    }
+}
+
+/// Sutherland-Hodgman clipping of an open ring to the side of the meridian `longitude`.
+fn clip_ring(ring: &[Vec<f64>], longitude: f64, keep_east: bool) -> Vec<Vec<f64>> {
+    let inside =
+        |position: &Vec<f64>| (position[0] >= longitude) == keep_east || position[0] == longitude;
+    let mut clipped = Vec::new();
+    for (index, current) in ring.iter().enumerate() {
+        let previous = &ring[(index + ring.len() - 1) % ring.len()];
+        match (inside(previous), inside(current)) {
+            (true, true) => push_distinct(&mut clipped, current.clone()),
+            (true, false) => push_distinct(
+                &mut clipped,
+                interpolate_at_longitude(previous, current, longitude),
+            ),
+            (false, true) => {
+                push_distinct(
+                    &mut clipped,
+                    interpolate_at_longitude(previous, current, longitude),
+                );
+                push_distinct(&mut clipped, current.clone());
+            }
+            (false, false) => {}
+        }
+    }
+    clipped
+}
+
+/// The part of an open ring between two meridians, shifted by `shift` degrees
+/// and closed, `None` when less than a triangle is left.
+fn ring_window(ring: &[Vec<f64>], west: f64, east: f64, shift: f64) -> Option<Vec<Vec<f64>>> {
+    let mut clipped = clip_ring(&clip_ring(ring, west, true), east, false);
+    if clipped.len() > 1 && clipped.first() == clipped.last() {
+        clipped.pop();
+    }
+    if clipped.len() < 3 {
+        return None;
+    }
+    for position in clipped.iter_mut() {
+        position[0] += shift;
+    }
+    clipped.push(clipped[0].clone());
+    Some(clipped)
+}
+
+/// The pieces of a polygon cut at the antimeridian, `None` when it does not cross it.
+/// Polygons around a pole, whose longitudes do not close, are left as they are.
+fn split_polygon(rings: &Value) -> Option<Vec<Value>> {
+    let mut rings: Vec<Vec<Vec<f64>>> = rings
+        .as_array()?
+        .iter()
+        .map(positions)
+        .collect::<Option<_>>()?;
+    for ring in rings.iter_mut() {
+        unwrap_longitudes(ring);
+        if ring.len() < 4 || ring.first() != ring.last() {
+            return None;
+        }
+        ring.pop();
+    }
+    let exterior = rings.first()?;
+    let (west, east) = exterior
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(w, e), p| {
+            (w.min(p[0]), e.max(p[0]))
+        });
+    if west >= -180.0 && east <= 180.0 {
+        return None;
+    }
+    // Holes are moved next to their exterior ring
+    for hole in rings.iter_mut().skip(1) {
+        let shift = 360.0 * ((west - hole[0][0]) / 360.0).ceil();
+        for position in hole.iter_mut() {
+            position[0] += shift;
+        }
+    }
+
+    let first_window = ((west + 180.0) / 360.0).floor() as i64;
+    let last_window = ((east - 180.0) / 360.0).ceil() as i64;
+    let mut pieces = Vec::new();
+    for window in first_window..=last_window {
+        let shift = -360.0 * window as f64;
+        let (window_west, window_east) = (-180.0 - shift, 180.0 - shift);
+        let Some(exterior) = ring_window(&rings[0], window_west, window_east, shift) else {
+            continue;
+        };
+        let mut piece = vec![positions_to_value(&exterior)];
+        for hole in rings.iter().skip(1) {
+            if let Some(hole) = ring_window(hole, window_west, window_east, shift) {
+                piece.push(positions_to_value(&hole));
+            }
+        }
+        pieces.push(piece.into_iter().collect::<Array>().into_value());
+    }
+    Some(pieces)
+}
+
+/// Simplify every LineString, polygon ring and Multi* part of a geometry.
+/// Rings stay closed and never drop below 4 positions, lines never drop below 2.
+pub fn simplify_geometry(geometry: &mut Value, simplification: &Simplification) {
+    info!("Starting geometry simplification: {:?}", simplification);
+    let geometry_type = geometry
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .to_string();
+    if geometry_type == "GeometryCollection" {
+        for_each_geometry(geometry, |member| simplify_geometry(member, simplification));
+        return;
+    }
+    let Some(coordinates) = geometry.get_mut("coordinates") else {
+        return;
+    };
+
+    match geometry_type.as_str() {
+        "LineString" => simplify_part(coordinates, simplification, 2),
+        "MultiLineString" => {
+            for_each_part(coordinates, |line| simplify_part(line, simplification, 2))
+        }
+        "Polygon" => for_each_part(coordinates, |ring| simplify_part(ring, simplification, 4)),
+        "MultiPolygon" => for_each_part(coordinates, |polygon| {
+            for_each_part(polygon, |ring| simplify_part(ring, simplification, 4))
+        }),
+        _ => debug!("Nothing to simplify for geometry type: {}", geometry_type),
+    }
+    info!("Geometry simplification completed");
+}
+
+/// Run `f` on each member of a GeometryCollection.
+pub(crate) fn for_each_geometry<F: FnMut(&mut Value)>(collection: &mut Value, mut f: F) {
+    if let Some(geometries) = collection
+        .get_mut("geometries")
+        .and_then(|g| g.as_array_mut())
+    {
+        for geometry in geometries.iter_mut() {
+            f(geometry);
+        }
+    }
+}
+
+fn for_each_part<F: FnMut(&mut Value)>(parts: &mut Value, mut f: F) {
+    if let Some(parts_array) = parts.as_array_mut() {
+        for part in parts_array.iter_mut() {
+            f(part);
+        }
+    }
+}
+
+/// Read the x/y of every position in an array of positions.
+/// Return None if one of the positions is not made of at least two numbers.
+fn part_to_points(part: &Value) -> Option<Vec<(f64, f64)>> {
+    part.as_array()?
+        .iter()
+        .map(|position| {
+            let x = position.get(0)?.as_f64()?;
+            let y = position.get(1)?.as_f64()?;
+            Some((x, y))
+        })
+        .collect()
+}
+
+/// Project lon/lat points to meters around their mean latitude (equirectangular).
+fn points_to_local_meters(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mean_latitude = points.iter().map(|(_, y)| y).sum::<f64>() / points.len() as f64;
+    let cos_latitude = mean_latitude.to_radians().cos();
+    points
+        .iter()
+        .map(|(x, y)| {
+            (
+                x.to_radians() * EARTH_RADIUS * cos_latitude,
+                y.to_radians() * EARTH_RADIUS,
+            )
+        })
+        .collect()
+}
+
+pub(crate) fn simplify_part(part: &mut Value, simplification: &Simplification, min_points: usize) {
+    let Some(points) = part_to_points(part) else {
+        debug!("Skipping part with invalid positions: {:?}", part);
+        return;
+    };
+    if points.len() <= min_points {
+        return;
+    }
+
+    let project = |points: Vec<(f64, f64)>, unit: &ToleranceUnit| match unit {
+        ToleranceUnit::Degrees => points,
+        ToleranceUnit::Meters => points_to_local_meters(&points),
+    };
+    let keep = match simplification {
+        Simplification::DouglasPeucker { tolerance, unit } => {
+            douglas_peucker(&project(points, unit), *tolerance, min_points)
+        }
+        Simplification::Visvalingam { threshold, unit } => {
+            let areas = visvalingam_whyatt(&project(points, unit));
+            match threshold {
+                VisvalingamThreshold::Area(area) => keep_significant(&areas, *area, min_points),
+                VisvalingamThreshold::KeepPercentage(percentage) => {
+                    let count = (areas.len() as f64 * percentage / 100.0).ceil() as usize;
+                    keep_most_significant(&areas, count.max(min_points))
+                }
+            }
+        }
+    };
+
+    if let Some(positions) = part.as_array_mut() {
+        let before = positions.len();
+        let mut keep_iter = keep.iter();
+        positions.retain(|_| *keep_iter.next().unwrap_or(&true));
+        debug!(
+            "Simplified part from {} to {} positions",
+            before,
+            positions.len()
+        );
+    }
+}
+
+fn perpendicular_distance(point: (f64, f64), start: (f64, f64), end: (f64, f64)) -> f64 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length_squared = dx * dx + dy * dy;
+    if length_squared == 0.0 {
+        return (point.0 - start.0).hypot(point.1 - start.1);
+    }
+    (dy * point.0 - dx * point.1 + end.0 * start.1 - end.1 * start.0).abs() / length_squared.sqrt()
+}
+
+/// Ramer-Douglas-Peucker simplification.
+/// Return which points to keep: the first and last points are always kept,
+/// and at least `min_points` points are kept, picking the most significant ones.
+pub fn douglas_peucker(points: &[(f64, f64)], tolerance: f64, min_points: usize) -> Vec<bool> {
+    let len = points.len();
+    if len <= min_points.max(2) {
+        return vec![true; len];
+    }
+
+    // The significance of a point is the distance at which it stops being kept,
+    // capped by the significance of the point that split its segment.
+    let mut significance = vec![0.0; len];
+    significance[0] = f64::INFINITY;
+    significance[len - 1] = f64::INFINITY;
+    let mut stack = vec![(0, len - 1, f64::INFINITY)];
+    while let Some((first, last, parent_significance)) = stack.pop() {
+        if last <= first + 1 {
+            continue;
+        }
+        let (index, distance) = (first + 1..last)
+            .map(|i| {
+                (
+                    i,
+                    perpendicular_distance(points[i], points[first], points[last]),
+                )
+            })
+            .fold((first + 1, -1.0), |max, current| {
+                if current.1 > max.1 {
+                    current
+                } else {
+                    max
+                }
+            });
+        let point_significance = distance.min(parent_significance);
+        significance[index] = point_significance;
+        stack.push((first, index, point_significance));
+        stack.push((index, last, point_significance));
+    }
+
+    keep_significant(&significance, tolerance, min_points)
+}
+
+/// Keep the points whose significance is above the threshold, or the
+/// `min_points` most significant ones if too few are above it.
+fn keep_significant(significance: &[f64], threshold: f64, min_points: usize) -> Vec<bool> {
+    let keep: Vec<bool> = significance.iter().map(|s| *s > threshold).collect();
+    if keep.iter().filter(|k| **k).count() < min_points {
+        return keep_most_significant(significance, min_points);
+    }
+    keep
+}
+
+fn keep_most_significant(significance: &[f64], count: usize) -> Vec<bool> {
+    let mut by_significance: Vec<usize> = (0..significance.len()).collect();
+    by_significance.sort_by(|a, b| significance[*b].total_cmp(&significance[*a]));
+    let mut keep = vec![false; significance.len()];
+    for index in by_significance.into_iter().take(count) {
+        keep[index] = true;
+    }
+    keep
+}
+
+fn triangle_area(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> f64 {
+    ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs() / 2.0
+}
+
+/// Entry of the Visvalingam-Whyatt heap, ordered by area (ties broken by index).
+#[derive(Debug, PartialEq)]
+struct EffectiveArea {
+    area: f64,
+    index: usize,
+}
+
+impl Eq for EffectiveArea {}
+
+impl PartialOrd for EffectiveArea {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for EffectiveArea {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.area
+            .total_cmp(&other.area)
+            .then(self.index.cmp(&other.index))
+    }
+}
+
+/// Visvalingam-Whyatt effective area of every point.
+/// The first and last points get an infinite area so they are always kept.
+/// Areas never decrease along the elimination order, like mapshaper does,
+/// so keeping the points above a threshold matches the iterative algorithm.
+pub fn visvalingam_whyatt(points: &[(f64, f64)]) -> Vec<f64> {
+    let len = points.len();
+    let mut areas = vec![f64::INFINITY; len];
+    if len < 3 {
+        return areas;
+    }
+
+    let mut previous: Vec<usize> = (0..len).map(|i| i.saturating_sub(1)).collect();
+    let mut next: Vec<usize> = (0..len).map(|i| (i + 1).min(len - 1)).collect();
+    let mut current_area = vec![f64::INFINITY; len];
+    let mut heap = BinaryHeap::new();
+    for i in 1..len - 1 {
+        current_area[i] = triangle_area(points[i - 1], points[i], points[i + 1]);
+        heap.push(Reverse(EffectiveArea {
+            area: current_area[i],
+            index: i,
+        }));
+    }
+
+    let mut max_area: f64 = 0.0;
+    while let Some(Reverse(EffectiveArea { area, index })) = heap.pop() {
+        // Skip the stale entries left behind when a neighbour area was updated
+        if !areas[index].is_infinite() || area != current_area[index] {
+            continue;
+        }
+        max_area = max_area.max(area);
+        areas[index] = max_area;
+
+        let (before, after) = (previous[index], next[index]);
+        next[before] = after;
+        previous[after] = before;
+        for neighbour in [before, after] {
+            if neighbour == 0 || neighbour == len - 1 {
+                continue;
+            }
+            current_area[neighbour] = triangle_area(
+                points[previous[neighbour]],
+                points[neighbour],
+                points[next[neighbour]],
+            );
+            heap.push(Reverse(EffectiveArea {
+                area: current_area[neighbour],
+                index: neighbour,
+            }));
+        }
+    }
+    areas
+}
+
+/// Remove the redundant vertices of every LineString and polygon ring: consecutive
+/// duplicates, and midpoints where the direction changes by at most `max_angle` degrees
+/// (0 only removes strictly collinear ones). Endpoints of lines are kept and rings stay
+/// closed with at least 4 positions. Returns the number of removed vertices.
+pub fn clean_geometry(geometry: &mut Value, max_angle: f64) -> usize {
+    let geometry_type = geometry
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let mut removed = 0;
+    if geometry_type == "GeometryCollection" {
+        for_each_geometry(geometry, |member| {
+            removed += clean_geometry(member, max_angle)
+        });
+        return removed;
+    }
+    let Some(coordinates) = geometry.get_mut("coordinates") else {
+        return 0;
+    };
+    let max_angle = max_angle.to_radians();
+
+    match geometry_type.as_str() {
+        "LineString" => removed += clean_line(coordinates, max_angle),
+        "MultiLineString" => {
+            for_each_part(coordinates, |line| removed += clean_line(line, max_angle))
+        }
+        "Polygon" => for_each_part(coordinates, |ring| removed += clean_ring(ring, max_angle)),
+        "MultiPolygon" => for_each_part(coordinates, |polygon| {
+            for_each_part(polygon, |ring| removed += clean_ring(ring, max_angle))
+        }),
+        _ => debug!("Nothing to clean for geometry type: {}", geometry_type),
+    }
+    if removed > 0 {
+        debug!(
+            "Removed {} redundant vertices from {}",
+            removed, geometry_type
+        );
+    }
+    removed
+}
+
+/// Rounded decimals are not exact in binary, so collinear vertices may turn by this angle.
+const COLLINEAR_ANGLE: f64 = 1e-9;
+
+/// Whether `b` can be removed between `a` and `c`: the line goes on in the same direction,
+/// turning by at most `max_angle` radians. Backtracking spikes are never redundant.
+fn is_redundant_vertex(a: (f64, f64), b: (f64, f64), c: (f64, f64), max_angle: f64) -> bool {
+    let (u, v) = ((b.0 - a.0, b.1 - a.1), (c.0 - b.0, c.1 - b.1));
+    let cross = u.0 * v.1 - u.1 * v.0;
+    let dot = u.0 * v.0 + u.1 * v.1;
+    if dot <= 0.0 {
+        return false;
+    }
+    cross.abs().atan2(dot) <= max_angle.max(COLLINEAR_ANGLE)
+}
+
+/// Indexes of the vertices to keep in a line, the first and last ones included.
+fn kept_vertices(points: &[(f64, f64)], max_angle: f64) -> Vec<usize> {
+    let mut kept: Vec<usize> = Vec::with_capacity(points.len());
+    for (index, point) in points.iter().enumerate() {
+        if kept.last().is_some_and(|last| points[*last] == *point) {
+            continue;
+        }
+        while let [.., a, b] = kept[..] {
+            if !is_redundant_vertex(points[a], points[b], *point, max_angle) {
+                break;
             }
+            kept.pop();
         }
-        if remove_null_properties {
-            remove_null_or_empty_properties(feature);
+        kept.push(index);
+    }
+    kept
+}
+
+/// Replace the positions of a part by the ones at `kept`, returning how many were removed.
+fn keep_positions(part: &mut Value, kept: &[usize]) -> usize {
+    let Some(array) = part.as_array_mut() else {
+        return 0;
+    };
+    let removed = array.len() - kept.len();
+    if removed > 0 {
+        let positions = std::mem::take(array);
+        *array = kept.iter().map(|index| positions[*index].clone()).collect();
+    }
+    removed
+}
+
+fn clean_line(line: &mut Value, max_angle: f64) -> usize {
+    let Some(points) = part_to_points(line) else {
+        return 0;
+    };
+    let kept = kept_vertices(&points, max_angle);
+    if kept.len() < 2 {
+        return 0;
+    }
+    keep_positions(line, &kept)
+}
+
+/// Unclosed rings are left to the repair step.
+fn clean_ring(ring: &mut Value, max_angle: f64) -> usize {
+    let Some(points) = part_to_points(ring) else {
+        return 0;
+    };
+    if points.len() < 4 || points.first() != points.last() {
+        return 0;
+    }
+    let mut kept = kept_vertices(&points[..points.len() - 1], max_angle);
+    // The first vertex is a midpoint too, between the last and the second ones
+    while kept.len() > 3 {
+        let (last, first, second) = (kept[kept.len() - 1], kept[0], kept[1]);
+        let before_last = kept[kept.len() - 2];
+        if points[last] == points[first]
+            || is_redundant_vertex(points[before_last], points[last], points[first], max_angle)
+        {
+            kept.pop();
+        } else if is_redundant_vertex(points[last], points[first], points[second], max_angle) {
+            kept.remove(0);
+        } else {
+            break;
         }
+    }
+    if kept.len() < 3 {
+        return 0;
+    }
+    kept.push(kept[0]);
+    // The closing position is a copy of the first one, not a removed vertex
+    keep_positions(ring, &kept)
+}
+
+/// Run the steps enabled by `options` on one feature.
+/// To process many features, build the [`Pipeline`] once instead.
+pub fn process_feature(feature: &mut Value, options: &MinifyOptions) {
+    Pipeline::from(options).apply(feature);
+}
+
+/// What to do with the `bbox` members of features, geometries and collections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BboxMode {
+    /// Keep the existing bboxes, rounded like the coordinates.
+    Keep,
+    /// Compute the bbox of every feature and of the collection.
+    Compute,
+    /// Remove every bbox.
+    Strip,
+}
+
+/// Starting point of [`geometry_bbox`]: contains nothing.
+pub const EMPTY_BBOX: [f64; 4] = [
+    f64::INFINITY,
+    f64::INFINITY,
+    f64::NEG_INFINITY,
+    f64::NEG_INFINITY,
+];
 
-        if let Some(keys) = properties_to_remove {
-            remove_properties(feature, keys);
+fn extend_bbox(coordinates: &Value, bbox: &mut [f64; 4]) {
+    let Some(array) = coordinates.as_array() else {
+        return;
+    };
+    if let (Some(x), Some(y)) = (
+        array.first().and_then(|x| x.as_f64()),
+        array.get(1).and_then(|y| y.as_f64()),
+    ) {
+        bbox[0] = bbox[0].min(x);
+        bbox[1] = bbox[1].min(y);
+        bbox[2] = bbox[2].max(x);
+        bbox[3] = bbox[3].max(y);
+        return;
+    }
+    for child in array.iter() {
+        extend_bbox(child, bbox);
+    }
+}
+
+/// Extend `bbox` ([west, south, east, north]) with the positions of a geometry.
+pub fn geometry_bbox(geometry: &Value, bbox: &mut [f64; 4]) {
+    if let Some(coordinates) = geometry.get("coordinates") {
+        extend_bbox(coordinates, bbox);
+    }
+    if let Some(geometries) = geometry.get("geometries").and_then(|g| g.as_array()) {
+        for child in geometries.iter() {
+            geometry_bbox(child, bbox);
         }
+    }
+}
 
-        if let Some(keys) = properties_to_keep {
-            keep_properties(feature, keys);
+/// Apply `mode` to the `bbox` member of a GeoJSON object.
+/// `compute` returns the new bbox, or `None` when the object has no position.
+pub fn update_bbox<F>(object: &mut Value, mode: BboxMode, rounding: &Rounding, compute: F)
+where
+    F: FnOnce(&Value) -> Option<[f64; 4]>,
+{
+    match mode {
+        BboxMode::Keep => {
+            if let Some(bbox) = object.get_mut("bbox") {
+                round_bbox(bbox, rounding);
+            }
+        }
+        BboxMode::Strip => {
+            if let Some(object) = object.as_object_mut() {
+                object.remove(&"bbox");
+            }
+        }
+        BboxMode::Compute => {
+            let bbox = compute(object);
+            let Some(object) = object.as_object_mut() else {
+                return;
+            };
+            match bbox {
+                Some(bbox) => {
+                    let mut bbox: Value = bbox
+                        .iter()
+                        .map(|edge| Value::new_f64(*edge).unwrap_or_default())
+                        .collect::<Array>()
+                        .into_value();
+                    round_bbox(&mut bbox, rounding);
+                    object.insert("bbox", bbox);
+                }
+                None => {
+                    object.remove(&"bbox");
+                }
+            }
         }
     }
 }
 
+/// The bbox of the positions of a geometry, `None` when it has none.
+pub fn compute_geometry_bbox(geometry: &Value) -> Option<[f64; 4]> {
+    let mut bbox = EMPTY_BBOX;
+    geometry_bbox(geometry, &mut bbox);
+    bbox[0].is_finite().then_some(bbox)
+}
 
-fn remove_null_or_empty_properties(geojson: &mut Value) {
+/// Apply `mode` to the bbox of a geometry and of the members of GeometryCollections.
+/// In compute mode, only the geometries that already have a bbox are updated.
+pub fn update_geometry_bboxes(geometry: &mut Value, mode: BboxMode, rounding: &Rounding) {
+    if mode != BboxMode::Compute || geometry.get("bbox").is_some() {
+        update_bbox(geometry, mode, rounding, compute_geometry_bbox);
+    }
+    for_each_geometry(geometry, |member| {
+        update_geometry_bboxes(member, mode, rounding)
+    });
+}
+
+/// Orientation of polygon rings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WindingOrder {
+    /// Counter-clockwise exterior rings and clockwise holes, as RFC 7946 requires.
+    Rfc7946,
+    /// Clockwise exterior rings and counter-clockwise holes, as d3 expects.
+    Clockwise,
+}
+
+/// Twice the signed area of a ring, positive when it is counter-clockwise.
+pub fn ring_signed_area(ring: &Value) -> f64 {
+    let Some(positions) = ring.as_array() else {
+        return 0.0;
+    };
+    let points: Vec<(f64, f64)> = positions
+        .iter()
+        .filter_map(|position| Some((position.get(0)?.as_f64()?, position.get(1)?.as_f64()?)))
+        .collect();
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+        .sum()
+}
+
+/// Reverse the rings of polygons that do not follow `order`, and return how many were reversed.
+/// GeometryCollections are rewound recursively.
+pub fn rewind_geometry(geometry: &mut Value, order: WindingOrder) -> usize {
+    let geometry_type = geometry
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let mut reversed = 0;
+    match geometry_type.as_str() {
+        "GeometryCollection" => {
+            for_each_geometry(geometry, |member| {
+                reversed += rewind_geometry(member, order)
+            });
+        }
+        "Polygon" => {
+            if let Some(rings) = geometry.get_mut("coordinates") {
+                reversed += rewind_polygon(rings, order);
+            }
+        }
+        "MultiPolygon" => {
+            if let Some(polygons) = geometry.get_mut("coordinates") {
+                for_each_part(polygons, |rings| reversed += rewind_polygon(rings, order));
+            }
+        }
+        _ => {}
+    }
+    if reversed > 0 {
+        debug!("Reversed {} rings of {}", reversed, geometry_type);
+    }
+    reversed
+}
+
+fn rewind_polygon(rings: &mut Value, order: WindingOrder) -> usize {
+    let mut reversed = 0;
+    let mut index = 0;
+    for_each_part(rings, |ring| {
+        let exterior = index == 0;
+        index += 1;
+        let area = ring_signed_area(ring);
+        let counter_clockwise = exterior == (order == WindingOrder::Rfc7946);
+        if area != 0.0 && (area > 0.0) != counter_clockwise {
+            if let Some(positions) = ring.as_array_mut() {
+                positions.reverse();
+                reversed += 1;
+            }
+        }
+    });
+    reversed
+}
+
+/// What [`fix_geometry`] changed in a geometry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FixSummary {
+    /// Positions whose latitude and longitude were swapped.
+    pub swapped_positions: usize,
+    /// Positions moved back into [-180, 180] x [-90, 90].
+    pub clamped_positions: usize,
+    /// Positions that were not two finite numbers or more.
+    pub removed_invalid_positions: usize,
+    pub removed_duplicate_vertices: usize,
+    pub closed_rings: usize,
+    /// Rings left with fewer than four positions.
+    pub removed_rings: usize,
+    /// Lines, polygons and points removed from Multi* geometries once empty.
+    pub removed_empty_parts: usize,
+}
+
+impl FixSummary {
+    pub fn is_empty(&self) -> bool {
+        *self == FixSummary::default()
+    }
+
+    pub fn add(&mut self, other: &FixSummary) {
+        self.swapped_positions += other.swapped_positions;
+        self.clamped_positions += other.clamped_positions;
+        self.removed_invalid_positions += other.removed_invalid_positions;
+        self.removed_duplicate_vertices += other.removed_duplicate_vertices;
+        self.closed_rings += other.closed_rings;
+        self.removed_rings += other.removed_rings;
+        self.removed_empty_parts += other.removed_empty_parts;
+    }
+}
+
+impl fmt::Display for FixSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let changes = [
+            (self.swapped_positions, "swapped lat/lon positions"),
+            (self.clamped_positions, "out-of-range positions clamped"),
+            (self.removed_invalid_positions, "invalid positions removed"),
+            (
+                self.removed_duplicate_vertices,
+                "duplicate vertices removed",
+            ),
+            (self.closed_rings, "rings closed"),
+            (self.removed_rings, "degenerate rings removed"),
+            (self.removed_empty_parts, "empty parts removed"),
+        ];
+        let changes: Vec<String> = changes
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, change)| format!("{} {}", count, change))
+            .collect();
+        f.write_str(&changes.join(", "))
+    }
+}
+
+/// Repair the common defects of a geometry: swapped or out-of-range coordinates,
+/// invalid positions, duplicate consecutive vertices, unclosed or degenerate rings
+/// and empty parts of Multi* geometries. GeometryCollections are repaired recursively.
+/// Coordinates are only swapped and clamped when they are `geographic` longitudes and latitudes.
+pub fn fix_geometry(geometry: &mut Value, geographic: bool) -> FixSummary {
+    let mut summary = FixSummary::default();
+    let geometry_type = geometry
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .to_string();
+    if geometry_type == "GeometryCollection" {
+        for_each_geometry(geometry, |member| {
+            summary.add(&fix_geometry(member, geographic))
+        });
+        return summary;
+    }
+    let Some(coordinates) = geometry.get_mut("coordinates") else {
+        return summary;
+    };
+
+    match geometry_type.as_str() {
+        "Point" => {
+            fix_position(coordinates, &mut summary, geographic);
+        }
+        "MultiPoint" => fix_positions(coordinates, &mut summary, geographic),
+        "LineString" => fix_line(coordinates, &mut summary, geographic),
+        "MultiLineString" => retain_parts(coordinates, &mut summary, |line, summary| {
+            fix_line(line, summary, geographic);
+            part_len(line) >= 2
+        }),
+        "Polygon" => fix_polygon(coordinates, &mut summary, geographic),
+        "MultiPolygon" => retain_parts(coordinates, &mut summary, |polygon, summary| {
+            fix_polygon(polygon, summary, geographic);
+            part_len(polygon) > 0
+        }),
+        _ => debug!("Nothing to fix for geometry type: {}", geometry_type),
+    }
+    if !summary.is_empty() {
+        debug!("Fixed {}: {}", geometry_type, summary);
+    }
+    summary
+}
+
+fn part_len(part: &Value) -> usize {
+    part.as_array().map_or(0, |array| array.len())
+}
+
+/// Keep the parts for which `fix` returns true, counting the others as removed.
+fn retain_parts<F>(parts: &mut Value, summary: &mut FixSummary, mut fix: F)
+where
+    F: FnMut(&mut Value, &mut FixSummary) -> bool,
+{
+    if let Some(array) = parts.as_array_mut() {
+        let before = array.len();
+        let mut kept = Array::new();
+        for mut part in std::mem::take(array).into_iter() {
+            if fix(&mut part, summary) {
+                kept.push(part);
+            }
+        }
+        summary.removed_empty_parts += before - kept.len();
+        *array = kept;
+    }
+}
+
+/// Swap, clamp and validate one position; returns false when it cannot be repaired.
+fn fix_position(position: &mut Value, summary: &mut FixSummary, geographic: bool) -> bool {
+    let Some(array) = position.as_array_mut() else {
+        return false;
+    };
+    let (Some(mut lon), Some(mut lat)) = (
+        array.first().and_then(|n| n.as_f64()),
+        array.get(1).and_then(|n| n.as_f64()),
+    ) else {
+        return false;
+    };
+    if !lon.is_finite() || !lat.is_finite() {
+        return false;
+    }
+    if !geographic {
+        return true;
+    }
+    if lat.abs() > 90.0 && lat.abs() <= 180.0 && lon.abs() <= 90.0 {
+        std::mem::swap(&mut lon, &mut lat);
+        summary.swapped_positions += 1;
+    }
+    if lon.abs() > 180.0 || lat.abs() > 90.0 {
+        lon = (lon + 180.0).rem_euclid(360.0) - 180.0;
+        lat = lat.clamp(-90.0, 90.0);
+        summary.clamped_positions += 1;
+    }
+    if Some(lon) != array[0].as_f64() || Some(lat) != array[1].as_f64() {
+        array[0] = Value::new_f64(lon).unwrap_or_default();
+        array[1] = Value::new_f64(lat).unwrap_or_default();
+    }
+    true
+}
+
+/// Repair the positions of a list, and remove the ones that cannot be repaired.
+fn fix_positions(positions: &mut Value, summary: &mut FixSummary, geographic: bool) {
+    if let Some(array) = positions.as_array_mut() {
+        let before = array.len();
+        array.retain_mut(|position| fix_position(position, summary, geographic));
+        summary.removed_invalid_positions += before - array.len();
+    }
+}
+
+fn remove_duplicate_vertices(positions: &mut Value, summary: &mut FixSummary) {
+    if let Some(array) = positions.as_array_mut() {
+        let before = array.len();
+        let mut previous: Option<Value> = None;
+        array.retain(|position| {
+            let duplicate = previous.as_ref() == Some(position);
+            previous = Some(position.clone());
+            !duplicate
+        });
+        summary.removed_duplicate_vertices += before - array.len();
+    }
+}
+
+fn fix_line(line: &mut Value, summary: &mut FixSummary, geographic: bool) {
+    fix_positions(line, summary, geographic);
+    remove_duplicate_vertices(line, summary);
+}
+
+fn fix_ring(ring: &mut Value, summary: &mut FixSummary, geographic: bool) {
+    fix_line(ring, summary, geographic);
+    if let Some(array) = ring.as_array_mut() {
+        if let (Some(first), Some(last)) = (array.first(), array.last()) {
+            if array.len() > 1 && first != last {
+                let first = first.clone();
+                array.push(first);
+                summary.closed_rings += 1;
+            }
+        }
+    }
+}
+
+/// Repair the rings of a polygon. Without a valid exterior ring, the polygon is left empty.
+fn fix_polygon(rings: &mut Value, summary: &mut FixSummary, geographic: bool) {
+    let Some(array) = rings.as_array_mut() else {
+        return;
+    };
+    let before = array.len();
+    let mut kept = Array::new();
+    for (index, mut ring) in std::mem::take(array).into_iter().enumerate() {
+        fix_ring(&mut ring, summary, geographic);
+        if part_len(&ring) >= 4 {
+            kept.push(ring);
+        } else if index == 0 {
+            // The holes of a degenerate exterior ring have nothing left to cut
+            summary.removed_rings += before;
+            return;
+        }
+    }
+    summary.removed_rings += before - kept.len();
+    *array = kept;
+}
+
+/// Minimum area of a polygon part or hole.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AreaThreshold {
+    SquareMeters(f64),
+    /// Fraction of the area of the whole feature, written as a percentage: '1%'.
+    Fraction(f64),
+}
+
+impl AreaThreshold {
+    fn square_meters(&self, feature_area: f64) -> f64 {
+        match self {
+            AreaThreshold::SquareMeters(area) => *area,
+            AreaThreshold::Fraction(fraction) => fraction * feature_area,
+        }
+    }
+}
+
+impl FromStr for AreaThreshold {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parse = |number: &str| {
+            number
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite() && *n >= 0.0)
+                .ok_or_else(|| format!("invalid area threshold: {:?}", value))
+        };
+        match value.strip_suffix('%') {
+            Some(percentage) => Ok(AreaThreshold::Fraction(parse(percentage)? / 100.0)),
+            None => Ok(AreaThreshold::SquareMeters(parse(value)?)),
+        }
+    }
+}
+
+/// Area of a ring in square meters: on the sphere for longitudes and latitudes
+/// (as in d3 and turf), in the plane for projected coordinates.
+pub fn ring_area(ring: &Value, geographic: bool) -> f64 {
+    if !geographic {
+        return ring_signed_area(ring).abs() / 2.0;
+    }
+    let Some(mut points) = part_to_points(ring) else {
+        return 0.0;
+    };
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    let count = points.len();
+    if count < 3 {
+        return 0.0;
+    }
+    let sum: f64 = (0..count)
+        .map(|index| {
+            let lower = points[index];
+            let middle = points[(index + 1) % count];
+            let upper = points[(index + 2) % count];
+            (upper.0 - lower.0).to_radians() * middle.1.to_radians().sin()
+        })
+        .sum();
+    (sum * EARTH_RADIUS * EARTH_RADIUS / 2.0).abs()
+}
+
+/// Area of a polygon, its exterior ring minus its holes.
+fn polygon_area(rings: &Value, geographic: bool) -> f64 {
+    let Some(rings) = rings.as_array() else {
+        return 0.0;
+    };
+    let mut rings = rings.iter().map(|ring| ring_area(ring, geographic));
+    let exterior = rings.next().unwrap_or_default();
+    (exterior - rings.sum::<f64>()).max(0.0)
+}
+
+/// Area of the polygons of a geometry in square meters,
+/// `None` when it has no polygon and thus no meaningful area.
+pub fn geometry_area(geometry: &Value, geographic: bool) -> Option<f64> {
+    let coordinates = geometry.get("coordinates");
+    match geometry.get("type").and_then(|t| t.as_str()) {
+        Some("Polygon") => coordinates.map(|rings| polygon_area(rings, geographic)),
+        Some("MultiPolygon") => {
+            coordinates
+                .and_then(|polygons| polygons.as_array())
+                .map(|polygons| {
+                    polygons
+                        .iter()
+                        .map(|rings| polygon_area(rings, geographic))
+                        .sum()
+                })
+        }
+        Some("GeometryCollection") => geometry
+            .get("geometries")
+            .and_then(|g| g.as_array())?
+            .iter()
+            .filter_map(|member| geometry_area(member, geographic))
+            .reduce(|total, area| total + area),
+        _ => None,
+    }
+}
+
+/// Remove the parts of MultiPolygons smaller than `min_part` (the largest part is always kept)
+/// and the holes smaller than `min_hole`. Fractions are relative to the area of the whole
+/// geometry, GeometryCollections included. Returns the number of removed parts and holes.
+pub fn remove_small_parts(
+    geometry: &mut Value,
+    min_part: Option<AreaThreshold>,
+    min_hole: Option<AreaThreshold>,
+    geographic: bool,
+) -> usize {
+    let Some(total_area) = geometry_area(geometry, geographic) else {
+        return 0;
+    };
+    let min_part = min_part.map(|threshold| threshold.square_meters(total_area));
+    let min_hole = min_hole.map(|threshold| threshold.square_meters(total_area));
+    let removed = remove_parts_below(geometry, min_part, min_hole, geographic);
+    if removed > 0 {
+        debug!("Removed {} small parts and holes", removed);
+    }
+    removed
+}
+
+fn remove_parts_below(
+    geometry: &mut Value,
+    min_part: Option<f64>,
+    min_hole: Option<f64>,
+    geographic: bool,
+) -> usize {
+    let geometry_type = geometry
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let mut removed = 0;
+    if geometry_type == "GeometryCollection" {
+        for_each_geometry(geometry, |member| {
+            removed += remove_parts_below(member, min_part, min_hole, geographic)
+        });
+        return removed;
+    }
+    let Some(coordinates) = geometry.get_mut("coordinates") else {
+        return 0;
+    };
+    let remove_holes = |rings: &mut Value| match min_hole {
+        Some(min_hole) => remove_small_holes(rings, min_hole, geographic),
+        None => 0,
+    };
+    match geometry_type.as_str() {
+        "Polygon" => removed += remove_holes(coordinates),
+        "MultiPolygon" => {
+            if let (Some(min_part), Some(polygons)) = (min_part, coordinates.as_array_mut()) {
+                let areas: Vec<f64> = polygons
+                    .iter()
+                    .map(|rings| polygon_area(rings, geographic))
+                    .collect();
+                let largest = areas.iter().copied().fold(0.0, f64::max);
+                let before = polygons.len();
+                let mut index = 0;
+                polygons.retain(|_| {
+                    let area = areas[index];
+                    index += 1;
+                    area >= min_part || area == largest
+                });
+                removed += before - polygons.len();
+            }
+            for_each_part(coordinates, |rings| removed += remove_holes(rings));
+        }
+        _ => {}
+    }
+    removed
+}
+
+fn remove_small_holes(rings: &mut Value, min_hole: f64, geographic: bool) -> usize {
+    let Some(rings) = rings.as_array_mut() else {
+        return 0;
+    };
+    let before = rings.len();
+    let mut index = 0;
+    rings.retain(|ring| {
+        index += 1;
+        // The exterior ring is not a hole
+        index == 1 || ring_area(ring, geographic) >= min_hole
+    });
+    before - rings.len()
+}
+
+pub(crate) fn remove_null_or_empty_properties(geojson: &mut Value) {
     debug!("Removing null or empty properties");
-    println!("Removing null or empty properties");
     if let Some(properties) = geojson
         .get_mut("properties")
         .and_then(|p| p.as_object_mut())
@@ -82,30 +1549,26 @@ fn remove_null_or_empty_properties(geojson: &mut Value) {
             .collect();
         for key in keys_to_remove {
             debug!("Removing property: {}", key);
-            println!("Removing property: {}", key);
             properties.remove(&key);
         }
     }
 }
 
-fn remove_properties(geojson: &mut Value, keys: &Vec<String>) {
+pub(crate) fn remove_properties(geojson: &mut Value, keys: &Vec<String>) {
     debug!("Removing properties: {:?}", keys);
-    println!("Removing properties: {:?}", keys);
     if let Some(properties) = geojson
         .get_mut("properties")
         .and_then(|p| p.as_object_mut())
     {
         for key in keys {
-            println!("Removing property: {}", key);
             debug!("Removing property: {}", key);
             properties.remove(&key);
         }
     }
 }
 
-fn keep_properties(geojson: &mut Value, keys: &Vec<String>) {
+pub(crate) fn keep_properties(geojson: &mut Value, keys: &Vec<String>) {
     debug!("Keeping properties: {:?}", keys);
-    println!("Keeping properties: {:?}", keys);
     if let Some(properties) = geojson
         .get_mut("properties")
         .and_then(|p| p.as_object_mut())
@@ -113,7 +1576,7 @@ fn keep_properties(geojson: &mut Value, keys: &Vec<String>) {
         let keys_to_remove: Vec<String> = properties
             .iter()
             .filter_map(|(key, _)| {
-                if !keys.contains(&key.to_string()){
+                if !keys.contains(&key.to_string()) {
                     Some(key.to_string())
                 } else {
                     None
@@ -121,7 +1584,6 @@ fn keep_properties(geojson: &mut Value, keys: &Vec<String>) {
             })
             .collect();
         for key in keys_to_remove {
-            println!("Removing property: {}", key);
             debug!("Removing property: {}", key);
             properties.remove(&key);
         }
@@ -144,6 +1606,240 @@ mod tests {
         assert_eq!(coordinates, json!([1.23, 2.35]));
     }
 
+    #[test]
+    fn test_round_geometry_to_meters() {
+        let mut geometry = json!({
+            "type": "MultiPoint",
+            "coordinates": [[12.3456789, 0.1234567], [12.3456789, 80.1234567, 123.456]]
+        });
+
+        let rounding = Rounding {
+            precision: Some(Precision::Meters(1.0)),
+            geographic: true,
+            ..Default::default()
+        };
+        round_geometry(&mut geometry, &rounding);
+
+        // Far from the equator, a degree of longitude is shorter and needs fewer decimals
+        assert_eq!(
+            geometry["coordinates"],
+            json!([[12.34568, 0.12346], [12.3457, 80.12346, 123.0]])
+        );
+        assert_eq!(decimals_for_resolution(0.01), 2);
+    }
+
+    #[test]
+    fn test_round_geometry_to_significant_digits() {
+        let mut geometry = json!({
+            "type": "LineString",
+            "coordinates": [[652470.64, 6862036.8], [0.0, -0.000123456]]
+        });
+
+        let rounding = Rounding {
+            precision: Some(Precision::SignificantDigits(7)),
+            ..Default::default()
+        };
+        round_geometry(&mut geometry, &rounding);
+
+        assert_eq!(
+            geometry["coordinates"],
+            json!([[652470.6, 6862037.0], [0.0, -0.0001234560]])
+        );
+        assert_eq!(round_significant(6862036.8, 3), 6860000.0);
+        assert_eq!(truncate_coord(0.1234567, 25), 0.1234567);
+    }
+
+    #[test]
+    fn test_round_geometry_with_own_z_and_m_decimals() {
+        let mut geometry = json!({
+            "type": "LineString",
+            "bbox": [1.0, 2.0, 10.0, 3.0, 4.0, 20.0],
+            "coordinates": [[1.23456, 2.34567, 10.123456789012, 5.55], [3.0, 4.0, 20.0, 6.0]]
+        });
+        let mut rounding = Rounding {
+            decimal: Some(1),
+            z_decimal: Some(2),
+            ..Default::default()
+        };
+
+        round_geometry(&mut geometry, &rounding);
+        assert_eq!(geometry["coordinates"][0], json!([1.2, 2.3, 10.12, 5.6]));
+
+        rounding.force_2d = true;
+        round_geometry(&mut geometry, &rounding);
+        assert_eq!(geometry["coordinates"], json!([[1.2, 2.3], [3.0, 4.0]]));
+        assert_eq!(geometry["bbox"], json!([1.0, 2.0, 3.0, 4.0]));
+    }
+
+    #[test]
+    fn test_round_bbox_like_the_coordinates() {
+        let mut bbox = json!([12.3456789, 0.1234567, 12.3456789, 80.1234567]);
+        let rounding = Rounding {
+            precision: Some(Precision::Meters(1.0)),
+            geographic: true,
+            ..Default::default()
+        };
+        round_bbox(&mut bbox, &rounding);
+        // The longitudes get the decimals needed at the northern edge
+        assert_eq!(bbox, json!([12.3457, 0.12346, 12.3457, 80.12346]));
+
+        let mut bbox = json!([1.23456, 2.34567, 10.123456, 3.0, 4.0, 20.987654]);
+        let rounding = Rounding {
+            precision: Some(Precision::SignificantDigits(3)),
+            z_decimal: Some(1),
+            ..Default::default()
+        };
+        round_bbox(&mut bbox, &rounding);
+        assert_eq!(bbox, json!([1.23, 2.35, 10.1, 3.0, 4.0, 21.0]));
+    }
+
+    #[test]
+    fn test_clean_geometry_removes_duplicate_and_collinear_vertices() {
+        let mut line = json!({
+            "type": "LineString",
+            "coordinates": [[0.0, 0.0], [1.0, 0.0], [1.0, 0.0], [2.0, 0.0], [2.0, 1.0], [1.0, 1.0]]
+        });
+        assert_eq!(clean_geometry(&mut line, 0.0), 2);
+        assert_eq!(
+            line["coordinates"],
+            json!([[0.0, 0.0], [2.0, 0.0], [2.0, 1.0], [1.0, 1.0]])
+        );
+
+        // The first vertex of the ring is a collinear midpoint too
+        let mut polygon = json!({
+            "type": "Polygon",
+            "coordinates": [[[1.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0], [0.0, 0.0], [1.0, 0.0]]]
+        });
+        assert_eq!(clean_geometry(&mut polygon, 0.0), 1);
+        assert_eq!(
+            polygon["coordinates"][0],
+            json!([[2.0, 0.0], [2.0, 2.0], [0.0, 2.0], [0.0, 0.0], [2.0, 0.0]])
+        );
+    }
+
+    #[test]
+    fn test_clean_geometry_with_angle_tolerance_keeps_rings_valid() {
+        let mut line = json!({
+            "type": "LineString",
+            "coordinates": [[0.0, 0.0], [1.0, 0.001], [2.0, 0.0], [2.0, 1.0], [1.0, 0.0]]
+        });
+        assert_eq!(clean_geometry(&mut line, 0.0), 0);
+        assert_eq!(clean_geometry(&mut line, 1.0), 1);
+        // Backtracking spikes are not collinear midpoints
+        assert_eq!(
+            line["coordinates"],
+            json!([[0.0, 0.0], [2.0, 0.0], [2.0, 1.0], [1.0, 0.0]])
+        );
+
+        let mut flat = json!({
+            "type": "Polygon",
+            "coordinates": [[[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [0.0, 0.0]]]
+        });
+        assert_eq!(clean_geometry(&mut flat, 0.0), 0);
+        assert_eq!(part_len(&flat["coordinates"][0]), 4);
+    }
+
+    #[test]
+    fn test_remove_small_parts_and_holes() {
+        // A 1° square at the equator covers about 12,364 km²
+        let square = json!([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0]]);
+        let area = ring_area(&square, true);
+        assert!((area - 1.2364e10).abs() < 1e7, "{}", area);
+
+        let mut geometry = json!({
+            "type": "MultiPolygon",
+            "coordinates": [
+                [
+                    [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0]],
+                    [[0.1, 0.1], [0.1, 0.2], [0.2, 0.2], [0.2, 0.1], [0.1, 0.1]],
+                    [[0.5, 0.5], [0.5, 0.501], [0.501, 0.501], [0.501, 0.5], [0.5, 0.5]]
+                ],
+                [[[2.0, 0.0], [2.01, 0.0], [2.01, 0.01], [2.0, 0.0]]]
+            ]
+        });
+        let removed = remove_small_parts(
+            &mut geometry,
+            "1%".parse().ok(),
+            Some(AreaThreshold::SquareMeters(1e6)),
+            true,
+        );
+
+        assert_eq!(removed, 2);
+        assert_eq!(geometry["coordinates"].as_array().unwrap().len(), 1);
+        assert_eq!(geometry["coordinates"][0].as_array().unwrap().len(), 2);
+        assert_eq!("0.5%".parse(), Ok(AreaThreshold::Fraction(0.005)));
+        assert!("-1".parse::<AreaThreshold>().is_err());
+    }
+
+    #[test]
+    fn test_split_antimeridian_cuts_lines() {
+        let mut line = json!({
+            "type": "LineString",
+            "coordinates": [[170.0, 0.0, 5.0], [-170.0, 10.0, 15.0], [-160.0, 10.0, 15.0]]
+        });
+
+        assert_eq!(split_antimeridian(&mut line), 1);
+        assert_eq!(line["type"].as_str(), Some("MultiLineString"));
+        assert_eq!(
+            line["coordinates"],
+            json!([
+                [[170.0, 0.0, 5.0], [180.0, 5.0, 10.0]],
+                [
+                    [-180.0, 5.0, 10.0],
+                    [-170.0, 10.0, 15.0],
+                    [-160.0, 10.0, 15.0]
+                ]
+            ])
+        );
+
+        let mut pacific =
+            json!({"type": "LineString", "coordinates": [[170.0, 0.0], [175.0, 1.0]]});
+        assert_eq!(split_antimeridian(&mut pacific), 0);
+        assert_eq!(pacific["type"].as_str(), Some("LineString"));
+    }
+
+    #[test]
+    fn test_split_antimeridian_cuts_polygons_and_their_holes() {
+        let mut polygon = json!({
+            "type": "Polygon",
+            "coordinates": [
+                [[170.0, -10.0], [-170.0, -10.0], [-170.0, 10.0], [170.0, 10.0], [170.0, -10.0]],
+                [[-175.0, -5.0], [-175.0, 5.0], [-172.0, 5.0], [-172.0, -5.0], [-175.0, -5.0]]
+            ]
+        });
+
+        assert_eq!(split_antimeridian(&mut polygon), 1);
+        assert_eq!(polygon["type"].as_str(), Some("MultiPolygon"));
+        assert_eq!(
+            polygon["coordinates"],
+            json!([
+                [[
+                    [170.0, -10.0],
+                    [180.0, -10.0],
+                    [180.0, 10.0],
+                    [170.0, 10.0],
+                    [170.0, -10.0]
+                ]],
+                [
+                    [
+                        [-180.0, -10.0],
+                        [-170.0, -10.0],
+                        [-170.0, 10.0],
+                        [-180.0, 10.0],
+                        [-180.0, -10.0]
+                    ],
+                    [
+                        [-175.0, -5.0],
+                        [-175.0, 5.0],
+                        [-172.0, 5.0],
+                        [-172.0, -5.0],
+                        [-175.0, -5.0]
+                    ]
+                ]
+            ])
+        );
+    }
+
     #[test]
     fn test_truncate_coordinate_in_array_of_array() {
         let mut coordinates = json!([[1.234567, 2.345678], [4.567890, 5.678901]]);
@@ -238,7 +1934,7 @@ mod tests {
             }
         });
 
-        process_feature(&mut geojson, Some(2), false, None, None);
+        process_feature(&mut geojson, &MinifyOptions::builder().decimal(2).build());
 
         assert_eq!(
             geojson,
@@ -274,7 +1970,12 @@ mod tests {
             }
         });
 
-        process_feature(&mut geojson, None, true, None, None);
+        process_feature(
+            &mut geojson,
+            &MinifyOptions::builder()
+                .remove_null_properties(true)
+                .build(),
+        );
 
         assert_eq!(
             geojson,
@@ -308,7 +2009,13 @@ mod tests {
             }
         });
 
-        process_feature(&mut geojson, Some(2), true, None, None);
+        process_feature(
+            &mut geojson,
+            &MinifyOptions::builder()
+                .decimal(2)
+                .remove_null_properties(true)
+                .build(),
+        );
 
         assert_eq!(
             geojson,
@@ -344,10 +2051,9 @@ mod tests {
 
         process_feature(
             &mut geojson,
-            None,
-            false,
-            Some(&vec!["name".to_string(), "empty".to_string()]),
-            None,
+            &MinifyOptions::builder()
+                .properties_to_remove(["name", "empty"])
+                .build(),
         );
 
         assert_eq!(
@@ -384,10 +2090,9 @@ mod tests {
 
         process_feature(
             &mut geojson,
-            None,
-            false,
-            None,
-            Some(&vec!["name".to_string(), "empty".to_string()]),
+            &MinifyOptions::builder()
+                .properties_to_keep(["name", "empty"])
+                .build(),
         );
 
         assert_eq!(
@@ -424,10 +2129,10 @@ mod tests {
 
         process_feature(
             &mut geojson,
-            Some(2),
-            false,
-            None,
-            Some(&vec!["name".to_string(), "empty".to_string()]),
+            &MinifyOptions::builder()
+                .decimal(2)
+                .properties_to_keep(["name", "empty"])
+                .build(),
         );
 
         assert_eq!(
@@ -445,4 +2150,220 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_douglas_peucker_removes_points_within_tolerance() {
+        let points = [
+            (0.0, 0.0),
+            (1.0, 0.1),
+            (2.0, -0.1),
+            (3.0, 5.0),
+            (4.0, 6.0),
+            (5.0, 7.0),
+        ];
+
+        let keep = douglas_peucker(&points, 1.0, 2);
+
+        assert_eq!(keep, vec![true, false, true, true, false, true]);
+    }
+
+    #[test]
+    fn test_simplify_polygon_keeps_ring_closed_with_four_positions() {
+        let mut geometry = json!({
+            "type": "Polygon",
+            "coordinates": [[
+                [0.0, 0.0],
+                [1.0, 0.001],
+                [2.0, 0.0],
+                [2.0, 2.0],
+                [0.0, 2.0],
+                [0.0, 0.0]
+            ]]
+        });
+        let simplification = Simplification::DouglasPeucker {
+            tolerance: 10.0,
+            unit: ToleranceUnit::Degrees,
+        };
+
+        simplify_geometry(&mut geometry, &simplification);
+
+        let ring = geometry["coordinates"][0].as_array().unwrap();
+        assert_eq!(ring.len(), 4);
+        assert_eq!(ring[0], ring[3]);
+    }
+
+    #[test]
+    fn test_visvalingam_whyatt_effective_areas() {
+        let points = [(0.0, 0.0), (1.0, 0.1), (2.0, 0.0), (3.0, 2.0), (4.0, 0.0)];
+
+        let areas = visvalingam_whyatt(&points);
+
+        assert!(areas[0].is_infinite() && areas[4].is_infinite());
+        assert!((areas[1] - 0.1).abs() < 1e-9);
+        assert!(areas[3] > areas[2] && areas[2] > areas[1]);
+    }
+
+    #[test]
+    fn test_simplify_polygon_with_visvalingam_keep_percentage() {
+        let mut geometry = json!({
+            "type": "MultiPolygon",
+            "coordinates": [[[
+                [0.0, 0.0],
+                [1.0, 0.01],
+                [2.0, 0.0],
+                [2.0, 1.0],
+                [2.01, 2.0],
+                [2.0, 3.0],
+                [0.0, 3.0],
+                [0.0, 0.0]
+            ]]]
+        });
+        let simplification = Simplification::Visvalingam {
+            threshold: VisvalingamThreshold::KeepPercentage(60.0),
+            unit: ToleranceUnit::Degrees,
+        };
+
+        simplify_geometry(&mut geometry, &simplification);
+
+        assert_eq!(
+            geometry["coordinates"],
+            json!([[[[0.0, 0.0], [2.0, 0.0], [2.0, 3.0], [0.0, 3.0], [0.0, 0.0]]]])
+        );
+    }
+
+    #[test]
+    fn test_simplify_line_string_with_meters_tolerance() {
+        let mut geometry = json!({
+            "type": "LineString",
+            "coordinates": [[2.0, 48.0], [2.5, 48.00001], [3.0, 48.0]]
+        });
+        let simplification = Simplification::DouglasPeucker {
+            tolerance: 5.0,
+            unit: ToleranceUnit::Meters,
+        };
+
+        simplify_geometry(&mut geometry, &simplification);
+
+        assert_eq!(geometry["coordinates"], json!([[2.0, 48.0], [3.0, 48.0]]));
+    }
+
+    #[test]
+    fn test_fix_geometry_repairs_rings_and_positions() {
+        let mut geometry = json!({
+            "type": "MultiPolygon",
+            "coordinates": [
+                [
+                    [[0.0, 0.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+                    [[0.2, 0.2], [0.3, 0.3], [0.2, 0.2]]
+                ],
+                [[[0.0, 0.0], [1.0, 1.0]]],
+                [[[46.5, 179.0], [1.0, "a"], [190.0, 10.0], [2.0, 12.0], [46.5, 179.0]]]
+            ]
+        });
+
+        let summary = fix_geometry(&mut geometry, true);
+
+        assert_eq!(
+            geometry["coordinates"],
+            json!([
+                [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0]]],
+                [[[179.0, 46.5], [-170.0, 10.0], [2.0, 12.0], [179.0, 46.5]]]
+            ])
+        );
+        assert_eq!(
+            summary,
+            FixSummary {
+                swapped_positions: 2,
+                clamped_positions: 1,
+                removed_invalid_positions: 1,
+                removed_duplicate_vertices: 1,
+                closed_rings: 2,
+                removed_rings: 2,
+                removed_empty_parts: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_fix_geometry_keeps_valid_geometries() {
+        let mut geometry = json!({
+            "type": "GeometryCollection",
+            "geometries": [
+                {"type": "Point", "coordinates": [2.35, 48.85]},
+                {"type": "LineString", "coordinates": [[0.0, 0.0], [1.0, 1.0]]}
+            ]
+        });
+        let expected = geometry.clone();
+
+        assert!(fix_geometry(&mut geometry, true).is_empty());
+        assert_eq!(geometry, expected);
+    }
+
+    #[test]
+    fn test_rewind_geometry_follows_the_winding_order() {
+        let clockwise = json!([[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]]);
+        let hole = json!([[0.2, 0.2], [0.8, 0.2], [0.8, 0.8], [0.2, 0.8], [0.2, 0.2]]);
+        let mut geometry = json!({
+            "type": "MultiPolygon",
+            "coordinates": [[clockwise.clone(), hole.clone()]]
+        });
+
+        assert_eq!(rewind_geometry(&mut geometry, WindingOrder::Rfc7946), 2);
+        let polygon = &geometry["coordinates"][0];
+        assert!(ring_signed_area(&polygon[0]) > 0.0);
+        assert!(ring_signed_area(&polygon[1]) < 0.0);
+        assert_eq!(rewind_geometry(&mut geometry, WindingOrder::Rfc7946), 0);
+
+        assert_eq!(rewind_geometry(&mut geometry, WindingOrder::Clockwise), 2);
+        assert_eq!(geometry["coordinates"][0], json!([clockwise, hole]));
+    }
+
+    #[test]
+    fn test_update_bbox_modes() {
+        let mut feature = json!({
+            "type": "Feature",
+            "bbox": [0.123456, 0.123456, 2.987654, 2.987654],
+            "geometry": {
+                "type": "GeometryCollection",
+                "bbox": [0.0, 0.0, 1.0, 1.0],
+                "geometries": [
+                    {"type": "Point", "coordinates": [0.5, 2.5]},
+                    {"type": "LineString", "coordinates": [[-1.0, 0.25], [1.5, 0.75]]}
+                ]
+            }
+        });
+        let compute = |feature: &Value| compute_geometry_bbox(&feature["geometry"]);
+
+        let mut kept = feature.clone();
+        let rounding = Rounding {
+            decimal: Some(2),
+            ..Default::default()
+        };
+        update_bbox(&mut kept, BboxMode::Keep, &rounding, compute);
+        assert_eq!(kept["bbox"], json!([0.12, 0.12, 2.99, 2.99]));
+
+        update_geometry_bboxes(
+            &mut feature["geometry"],
+            BboxMode::Compute,
+            &Rounding::default(),
+        );
+        update_bbox(
+            &mut feature,
+            BboxMode::Compute,
+            &Rounding::default(),
+            compute,
+        );
+        assert_eq!(feature["bbox"], json!([-1.0, 0.25, 1.5, 2.5]));
+        assert_eq!(feature["geometry"]["bbox"], json!([-1.0, 0.25, 1.5, 2.5]));
+        assert!(feature["geometry"]["geometries"][0].get("bbox").is_none());
+
+        update_geometry_bboxes(
+            &mut feature["geometry"],
+            BboxMode::Strip,
+            &Rounding::default(),
+        );
+        update_bbox(&mut feature, BboxMode::Strip, &Rounding::default(), compute);
+        assert!(feature.get("bbox").is_none());
+        assert!(feature["geometry"].get("bbox").is_none());
+    }
 }
@@ -0,0 +1,584 @@
+use crate::file_operations::MyError;
+use log::debug;
+use sonic_rs::{
+    to_string, to_value, Array, JsonContainerTrait, JsonValueTrait, Number, Object, Value,
+};
+use std::fmt;
+use std::str::FromStr;
+
+/// A position: longitude, latitude and optional elevation or measure.
+/// The numbers keep their representation, so `1.0` is written back as `1.0` and `1` as `1`.
+pub type Position = Vec<Number>;
+
+/// Any RFC 7946 GeoJSON object.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoJson {
+    Geometry(Geometry),
+    Feature(Feature),
+    FeatureCollection(FeatureCollection),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FeatureCollection {
+    pub features: Vec<Feature>,
+    pub bbox: Option<Vec<Number>>,
+    /// Members not defined by RFC 7946 (`name`, `crs`, ...), in their original order.
+    pub foreign_members: Object,
+    /// Keys of the parsed object in their original order, empty for a built object.
+    pub member_order: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Feature {
+    pub id: Option<FeatureId>,
+    /// `None` for a `null` geometry.
+    pub geometry: Option<Geometry>,
+    /// `None` for `null` properties.
+    pub properties: Option<Object>,
+    pub bbox: Option<Vec<Number>>,
+    pub foreign_members: Object,
+    /// Keys of the parsed object in their original order, empty for a built object.
+    /// A `geometry` or `properties` member missing from it is not written back.
+    pub member_order: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeatureId {
+    String(String),
+    Number(sonic_rs::Number),
+}
+
+/// A geometry with its optional bbox and foreign members.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geometry {
+    pub value: GeometryValue,
+    pub bbox: Option<Vec<Number>>,
+    pub foreign_members: Object,
+    /// Keys of the parsed object in their original order, empty for a built object.
+    pub member_order: Vec<String>,
+}
+
+/// The seven RFC 7946 geometry types.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeometryValue {
+    Point(Position),
+    MultiPoint(Vec<Position>),
+    LineString(Vec<Position>),
+    MultiLineString(Vec<Vec<Position>>),
+    Polygon(Vec<Vec<Position>>),
+    MultiPolygon(Vec<Vec<Vec<Position>>>),
+    GeometryCollection(Vec<Geometry>),
+}
+
+impl GeometryValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            GeometryValue::Point(_) => "Point",
+            GeometryValue::MultiPoint(_) => "MultiPoint",
+            GeometryValue::LineString(_) => "LineString",
+            GeometryValue::MultiLineString(_) => "MultiLineString",
+            GeometryValue::Polygon(_) => "Polygon",
+            GeometryValue::MultiPolygon(_) => "MultiPolygon",
+            GeometryValue::GeometryCollection(_) => "GeometryCollection",
+        }
+    }
+}
+
+impl Geometry {
+    pub fn new(value: GeometryValue) -> Self {
+        Geometry {
+            value,
+            bbox: None,
+            foreign_members: Object::new(),
+            member_order: Vec::new(),
+        }
+    }
+}
+
+/// Build a position from its ordinates, whole numbers are written without a fractional part.
+/// `None` when an ordinate is not finite, as JSON cannot represent it.
+pub fn position(ordinates: &[f64]) -> Option<Position> {
+    ordinates
+        .iter()
+        .map(|&ordinate| {
+            if ordinate.fract() == 0.0 && ordinate.abs() < i64::MAX as f64 {
+                Some(Number::from(ordinate as i64))
+            } else {
+                Number::from_f64(ordinate)
+            }
+        })
+        .collect()
+}
+
+impl Feature {
+    pub fn from_geometry(geometry: Geometry) -> Self {
+        Feature {
+            geometry: Some(geometry),
+            ..Default::default()
+        }
+    }
+}
+
+impl FeatureCollection {
+    pub fn new(features: Vec<Feature>) -> Self {
+        FeatureCollection {
+            features,
+            ..Default::default()
+        }
+    }
+}
+
+fn invalid(message: String) -> MyError {
+    MyError::InvalidGeoJson(message)
+}
+
+fn expect_object<'a>(value: &'a Value, what: &str) -> Result<&'a Object, MyError> {
+    value
+        .as_object()
+        .ok_or_else(|| invalid(format!("{} must be an object", what)))
+}
+
+fn expect_array<'a>(value: &'a Value, what: &str) -> Result<&'a Array, MyError> {
+    value
+        .as_array()
+        .ok_or_else(|| invalid(format!("{} must be an array", what)))
+}
+
+fn object_type(object: &Object) -> Result<&str, MyError> {
+    object
+        .get(&"type")
+        .and_then(|kind| kind.as_str())
+        .ok_or_else(|| invalid("missing \"type\" member".to_string()))
+}
+
+/// Copy the members of `object` that are not in `known`.
+fn foreign_members(object: &Object, known: &[&str]) -> Object {
+    let mut foreign = Object::new();
+    for (key, value) in object.iter() {
+        if !known.contains(&key) {
+            foreign.insert(key, value.clone());
+        }
+    }
+    foreign
+}
+
+fn member_order(object: &Object) -> Vec<String> {
+    object.iter().map(|(key, _)| key.to_string()).collect()
+}
+
+fn parse_bbox(object: &Object) -> Result<Option<Vec<Number>>, MyError> {
+    match object.get(&"bbox") {
+        None => Ok(None),
+        Some(bbox) => {
+            let values = expect_array(bbox, "bbox")?;
+            let bbox = values
+                .iter()
+                .map(|value| value.as_number())
+                .collect::<Option<Vec<Number>>>()
+                .ok_or_else(|| invalid("bbox must only contain numbers".to_string()))?;
+            Ok(Some(bbox))
+        }
+    }
+}
+
+fn parse_position(value: &Value) -> Result<Position, MyError> {
+    let position = expect_array(value, "a position")?
+        .iter()
+        .map(|number| number.as_number())
+        .collect::<Option<Position>>()
+        .ok_or_else(|| invalid("a position must only contain numbers".to_string()))?;
+    if position.len() < 2 {
+        return Err(invalid("a position needs at least two numbers".to_string()));
+    }
+    Ok(position)
+}
+
+fn parse_positions(value: &Value) -> Result<Vec<Position>, MyError> {
+    expect_array(value, "coordinates")?
+        .iter()
+        .map(parse_position)
+        .collect()
+}
+
+fn parse_rings(value: &Value) -> Result<Vec<Vec<Position>>, MyError> {
+    expect_array(value, "coordinates")?
+        .iter()
+        .map(parse_positions)
+        .collect()
+}
+
+fn parse_geometry(value: &Value) -> Result<Geometry, MyError> {
+    let object = expect_object(value, "a geometry")?;
+    let kind = object_type(object)?;
+    let coordinates = || {
+        object
+            .get(&"coordinates")
+            .ok_or_else(|| invalid(format!("{} without \"coordinates\"", kind)))
+    };
+    let value = match kind {
+        "Point" => GeometryValue::Point(parse_position(coordinates()?)?),
+        "MultiPoint" => GeometryValue::MultiPoint(parse_positions(coordinates()?)?),
+        "LineString" => GeometryValue::LineString(parse_positions(coordinates()?)?),
+        "MultiLineString" => GeometryValue::MultiLineString(parse_rings(coordinates()?)?),
+        "Polygon" => GeometryValue::Polygon(parse_rings(coordinates()?)?),
+        "MultiPolygon" => GeometryValue::MultiPolygon(
+            expect_array(coordinates()?, "coordinates")?
+                .iter()
+                .map(parse_rings)
+                .collect::<Result<_, _>>()?,
+        ),
+        "GeometryCollection" => {
+            let geometries = object
+                .get(&"geometries")
+                .ok_or_else(|| invalid("GeometryCollection without \"geometries\"".to_string()))?;
+            GeometryValue::GeometryCollection(
+                expect_array(geometries, "geometries")?
+                    .iter()
+                    .map(parse_geometry)
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        other => return Err(invalid(format!("unknown geometry type \"{}\"", other))),
+    };
+    let known: &[&str] = match value {
+        GeometryValue::GeometryCollection(_) => &["type", "geometries", "bbox"],
+        _ => &["type", "coordinates", "bbox"],
+    };
+    Ok(Geometry {
+        value,
+        bbox: parse_bbox(object)?,
+        foreign_members: foreign_members(object, known),
+        member_order: member_order(object),
+    })
+}
+
+fn parse_feature(value: &Value) -> Result<Feature, MyError> {
+    let object = expect_object(value, "a feature")?;
+    if object_type(object)? != "Feature" {
+        return Err(invalid("expected a Feature".to_string()));
+    }
+    let id = match object.get(&"id") {
+        None => None,
+        Some(id) => {
+            if let Some(id) = id.as_str() {
+                Some(FeatureId::String(id.to_string()))
+            } else if let Some(id) = id.as_number() {
+                Some(FeatureId::Number(id))
+            } else {
+                return Err(invalid(
+                    "a feature id must be a string or a number".to_string(),
+                ));
+            }
+        }
+    };
+    let geometry = match object.get(&"geometry") {
+        Some(geometry) if !geometry.is_null() => Some(parse_geometry(geometry)?),
+        _ => None,
+    };
+    let properties = match object.get(&"properties") {
+        Some(properties) if !properties.is_null() => {
+            Some(expect_object(properties, "properties")?.clone())
+        }
+        _ => None,
+    };
+    Ok(Feature {
+        id,
+        geometry,
+        properties,
+        bbox: parse_bbox(object)?,
+        foreign_members: foreign_members(object, &["type", "id", "geometry", "properties", "bbox"]),
+        member_order: member_order(object),
+    })
+}
+
+fn parse_feature_collection(object: &Object) -> Result<FeatureCollection, MyError> {
+    let features = object
+        .get(&"features")
+        .ok_or_else(|| invalid("FeatureCollection without \"features\"".to_string()))?;
+    Ok(FeatureCollection {
+        features: expect_array(features, "features")?
+            .iter()
+            .map(parse_feature)
+            .collect::<Result<_, _>>()?,
+        bbox: parse_bbox(object)?,
+        foreign_members: foreign_members(object, &["type", "features", "bbox"]),
+        member_order: member_order(object),
+    })
+}
+
+impl TryFrom<&Value> for GeoJson {
+    type Error = MyError;
+
+    fn try_from(value: &Value) -> Result<Self, MyError> {
+        let object = expect_object(value, "a GeoJSON document")?;
+        let geojson = match object_type(object)? {
+            "FeatureCollection" => GeoJson::FeatureCollection(parse_feature_collection(object)?),
+            "Feature" => GeoJson::Feature(parse_feature(value)?),
+            _ => GeoJson::Geometry(parse_geometry(value)?),
+        };
+        debug!("Parsed a typed GeoJSON {}", geojson.type_name());
+        Ok(geojson)
+    }
+}
+
+impl FromStr for GeoJson {
+    type Err = MyError;
+
+    fn from_str(s: &str) -> Result<Self, MyError> {
+        // sonic-rs reads -0.0 as 0.0, serde_json keeps its sign
+        let value: serde_json::Value = serde_json::from_str(s)?;
+        GeoJson::try_from(&to_value(&value)?)
+    }
+}
+
+impl GeoJson {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            GeoJson::Geometry(geometry) => geometry.value.type_name(),
+            GeoJson::Feature(_) => "Feature",
+            GeoJson::FeatureCollection(_) => "FeatureCollection",
+        }
+    }
+}
+
+fn position_value(position: &[Number]) -> Value {
+    position
+        .iter()
+        .cloned()
+        .map(Value::from)
+        .collect::<Array>()
+        .into_value()
+}
+
+fn positions_value(positions: &[Position]) -> Value {
+    positions
+        .iter()
+        .map(|position| position_value(position))
+        .collect::<Array>()
+        .into_value()
+}
+
+fn rings_value(rings: &[Vec<Position>]) -> Value {
+    rings
+        .iter()
+        .map(|ring| positions_value(ring))
+        .collect::<Array>()
+        .into_value()
+}
+
+fn push_bbox(members: &mut Vec<(&str, Value)>, bbox: &Option<Vec<Number>>) {
+    if let Some(bbox) = bbox {
+        members.push(("bbox", position_value(bbox)));
+    }
+}
+
+/// Whether a parsed object had no `key` member.
+fn is_missing(member_order: &[String], key: &str) -> bool {
+    !member_order.is_empty() && !member_order.iter().any(|member| member == key)
+}
+
+/// Write the RFC 7946 `members` and the foreign members of an object in `member_order`,
+/// then the members it does not list: the RFC 7946 ones first, in the order given.
+fn object_value(
+    members: Vec<(&str, Value)>,
+    foreign_members: &Object,
+    member_order: &[String],
+) -> Value {
+    let mut object = Object::new();
+    for key in member_order {
+        let value = members
+            .iter()
+            .find(|(member, _)| member == key)
+            .map(|(_, value)| value)
+            .or_else(|| foreign_members.get(&key.as_str()));
+        if let Some(value) = value {
+            object.insert(key, value.clone());
+        }
+    }
+    for (key, value) in members {
+        if object.get(&key).is_none() {
+            object.insert(key, value);
+        }
+    }
+    for (key, value) in foreign_members.iter() {
+        if object.get(&key).is_none() {
+            object.insert(key, value.clone());
+        }
+    }
+    object.into_value()
+}
+
+impl From<&Geometry> for Value {
+    fn from(geometry: &Geometry) -> Value {
+        let (key, value) = match &geometry.value {
+            GeometryValue::Point(position) => ("coordinates", position_value(position)),
+            GeometryValue::MultiPoint(positions) | GeometryValue::LineString(positions) => {
+                ("coordinates", positions_value(positions))
+            }
+            GeometryValue::MultiLineString(rings) | GeometryValue::Polygon(rings) => {
+                ("coordinates", rings_value(rings))
+            }
+            GeometryValue::MultiPolygon(polygons) => (
+                "coordinates",
+                polygons
+                    .iter()
+                    .map(|polygon| rings_value(polygon))
+                    .collect::<Array>()
+                    .into_value(),
+            ),
+            GeometryValue::GeometryCollection(geometries) => (
+                "geometries",
+                geometries
+                    .iter()
+                    .map(Value::from)
+                    .collect::<Array>()
+                    .into_value(),
+            ),
+        };
+        let mut members = vec![
+            ("type", Value::from(geometry.value.type_name())),
+            (key, value),
+        ];
+        push_bbox(&mut members, &geometry.bbox);
+        object_value(members, &geometry.foreign_members, &geometry.member_order)
+    }
+}
+
+impl From<&Feature> for Value {
+    fn from(feature: &Feature) -> Value {
+        let mut members = vec![("type", Value::from("Feature"))];
+        match &feature.id {
+            Some(FeatureId::String(id)) => members.push(("id", Value::from(id.as_str()))),
+            Some(FeatureId::Number(id)) => members.push(("id", Value::from(id.clone()))),
+            None => {}
+        }
+        if feature.properties.is_some() || !is_missing(&feature.member_order, "properties") {
+            let properties = feature.properties.clone().map(Object::into_value);
+            members.push(("properties", properties.unwrap_or_default()));
+        }
+        if feature.geometry.is_some() || !is_missing(&feature.member_order, "geometry") {
+            let geometry = feature.geometry.as_ref().map(Value::from);
+            members.push(("geometry", geometry.unwrap_or_default()));
+        }
+        push_bbox(&mut members, &feature.bbox);
+        object_value(members, &feature.foreign_members, &feature.member_order)
+    }
+}
+
+impl From<&FeatureCollection> for Value {
+    fn from(collection: &FeatureCollection) -> Value {
+        let features = collection
+            .features
+            .iter()
+            .map(Value::from)
+            .collect::<Array>()
+            .into_value();
+        let mut members = vec![("type", Value::from("FeatureCollection"))];
+        push_bbox(&mut members, &collection.bbox);
+        members.push(("features", features));
+        object_value(
+            members,
+            &collection.foreign_members,
+            &collection.member_order,
+        )
+    }
+}
+
+impl From<&GeoJson> for Value {
+    fn from(geojson: &GeoJson) -> Value {
+        match geojson {
+            GeoJson::Geometry(geometry) => geometry.into(),
+            GeoJson::Feature(feature) => feature.into(),
+            GeoJson::FeatureCollection(collection) => collection.into(),
+        }
+    }
+}
+
+impl fmt::Display for GeoJson {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let json = to_string(&Value::from(self)).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+impl serde::Serialize for GeoJson {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Value::from(self).serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sonic_rs::from_str;
+
+    #[test]
+    fn test_geojson_round_trip_keeps_every_member() {
+        let input = r#"{"type":"FeatureCollection","name":"test","features":[{"type":"Feature","id":7,"properties":{"name":"a","pop":null},"geometry":{"type":"Polygon","coordinates":[[[0,0],[1.5,0],[1.5,1],[0,0]]]},"style":"red"},{"type":"Feature","properties":null,"geometry":{"type":"GeometryCollection","geometries":[{"type":"Point","coordinates":[2.35,48.85,35]},{"type":"MultiLineString","coordinates":[[[0,0],[1,1]]]}]}},{"type":"Feature","id":"b","properties":{},"geometry":null,"bbox":[0,0,1,1]}]}"#;
+
+        let geojson: GeoJson = input.parse().unwrap();
+
+        let GeoJson::FeatureCollection(collection) = &geojson else {
+            panic!("expected a FeatureCollection");
+        };
+        assert_eq!(collection.features.len(), 3);
+        assert_eq!(
+            collection.features[0].id,
+            Some(FeatureId::Number(7u64.into()))
+        );
+        assert_eq!(
+            collection
+                .foreign_members
+                .get(&"name")
+                .and_then(|n| n.as_str()),
+            Some("test")
+        );
+        let Some(Geometry {
+            value: GeometryValue::GeometryCollection(geometries),
+            ..
+        }) = &collection.features[1].geometry
+        else {
+            panic!("expected a GeometryCollection");
+        };
+        assert_eq!(
+            geometries[0].value,
+            GeometryValue::Point(position(&[2.35, 48.85, 35.0]).unwrap())
+        );
+
+        let expected: Value = from_str(input).unwrap();
+        let written: Value = from_str(&geojson.to_string()).unwrap();
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn test_geojson_round_trip_is_exact() {
+        let input = r#"{"features":[{"type":"Feature","geometry":{"coordinates":[1.0,-0.0,2],"type":"Point"}},{"properties":{"a":1.0},"type":"Feature","geometry":null,"bbox":[0.0,-0.0,1,1.5]}],"name":"exact","type":"FeatureCollection"}"#;
+
+        let geojson: GeoJson = input.parse().unwrap();
+
+        assert_eq!(geojson.to_string(), input);
+        assert!(position(&[f64::NAN, 0.0]).is_none());
+    }
+
+    #[test]
+    fn test_geojson_rejects_invalid_objects() {
+        assert!(r#"{"type":"Point","coordinates":[1]}"#.parse::<GeoJson>().is_err());
+        assert!(r#"{"type":"Circle","coordinates":[0,0]}"#.parse::<GeoJson>().is_err());
+        assert!(r#"{"type":"FeatureCollection"}"#.parse::<GeoJson>().is_err());
+        assert!(r#"{"type":"Feature","id":true,"geometry":null}"#.parse::<GeoJson>().is_err());
+    }
+
+    #[test]
+    fn test_geojson_build_and_serialize() {
+        let feature = Feature::from_geometry(Geometry::new(GeometryValue::LineString(vec![
+            position(&[0.0, 0.0]).unwrap(),
+            position(&[1.25, 2.0]).unwrap(),
+        ])));
+        let geojson = GeoJson::FeatureCollection(FeatureCollection::new(vec![feature]));
+
+        assert_eq!(
+            geojson.to_string(),
+            r#"{"type":"FeatureCollection","features":[{"type":"Feature","properties":null,"geometry":{"type":"LineString","coordinates":[[0,0],[1.25,2]]}}]}"#
+        );
+    }
+}
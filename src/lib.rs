@@ -1,4 +1,16 @@
 pub mod args;
+pub mod clip;
 pub mod file_operations;
 pub mod geo_operations;
+pub mod geojson;
 pub mod metrics;
+pub mod minify;
+pub mod pipeline;
+pub mod projection;
+pub mod stream;
+pub mod topojson;
+pub mod topology;
+pub mod validation;
+
+pub use minify::{minify_reader_to_writer, minify_str, minify_value, MinifyOptions};
+pub use pipeline::{Pipeline, Transform};
@@ -1,29 +1,92 @@
 use clap::Parser;
 use log::{error, info};
 use mini_geojson::args::Args;
-use mini_geojson::file_operations::{handle_geojson_processing, handle_output_path};
+use mini_geojson::file_operations::{collect_input_files, handle_input_file, is_batch, InputFile};
 use mini_geojson::metrics::calculate_and_display_size_reduction;
+use mini_geojson::validation::{handle_input_validation, has_errors};
+use std::io::{self, Write};
+use std::process::ExitCode;
 
-fn main() {
+fn main() -> ExitCode {
     pretty_env_logger::init();
     let args = Args::parse();
+    if let Err(e) = args.check() {
+        e.exit();
+    }
     info!("Starting program with arguments: {:?}", args);
 
-    let output_path = match handle_output_path(&args) {
-        Ok(path) => path,
+    let inputs = match collect_input_files(&args) {
+        Ok(inputs) => inputs,
         Err(e) => {
-            error!("Error handling output path: {}", e);
-            return;
+            error!("Error collecting input files: {}", e);
+            return ExitCode::FAILURE;
         }
     };
+    if args.validate {
+        return validate_inputs(&inputs);
+    }
+    let batch = is_batch(&args, &inputs);
 
-    match handle_geojson_processing(&args, &output_path) {
-        Ok(()) => {
-            println!("Program completed successfully.");
-            calculate_and_display_size_reduction(&args.input, &output_path);
+    let mut processed = Vec::new();
+    let mut failures = Vec::new();
+    for input in &inputs {
+        match handle_input_file(&args, input, batch) {
+            Ok(sizes) => processed.push(sizes),
+            Err(e) => {
+                error!("Error processing GeoJSON {}: {}", input.path, e);
+                failures.push((input.path.clone(), e.to_string()));
+            }
         }
-        Err(e) => {
-            error!("Error processing GeoJSON: {}", e);
+    }
+
+    // The report goes to stderr when the output is written to stdout
+    let mut report: Box<dyn Write> = if args.writes_to_stdout() {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    };
+    if !processed.is_empty() {
+        let displayed = writeln!(report, "Program completed successfully.")
+            .and_then(|_| calculate_and_display_size_reduction(&processed, &mut report));
+        if let Err(e) = displayed {
+            error!("Error displaying the size reduction: {}", e);
         }
     }
+
+    if failures.is_empty() {
+        return ExitCode::SUCCESS;
+    }
+    eprintln!("{} of {} files failed:", failures.len(), inputs.len());
+    for (path, e) in &failures {
+        eprintln!("  {}: {}", path, e);
+    }
+    ExitCode::FAILURE
+}
+
+/// Print the problems of every input, and fail when one of them is invalid.
+fn validate_inputs(inputs: &[InputFile]) -> ExitCode {
+    let mut invalid = 0;
+    for input in inputs {
+        match handle_input_validation(&input.path) {
+            Ok(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    println!("{}: {}", input.path, diagnostic);
+                }
+                if has_errors(&diagnostics) {
+                    invalid += 1;
+                }
+            }
+            Err(e) => {
+                println!("{}: error at /: {}", input.path, e);
+                invalid += 1;
+            }
+        }
+    }
+
+    if invalid == 0 {
+        println!("{} valid files.", inputs.len());
+        return ExitCode::SUCCESS;
+    }
+    println!("{} of {} files are invalid.", invalid, inputs.len());
+    ExitCode::FAILURE
 }
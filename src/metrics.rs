@@ -1,10 +1,65 @@
-use std::{fs, path::Path};
+use std::io::{self, Read, Write};
 
 use size::Size;
 
-fn get_file_size(file_path: &str) -> std::io::Result<u64> {
-    let metadata = fs::metadata(file_path)?;
-    Ok(metadata.len())
+/// Sizes in bytes of an input and of its minified output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSizes {
+    pub name: String,
+    pub original_size: u64,
+    pub minified_size: u64,
+}
+
+/// Reader counting the bytes read, to measure inputs that are not files (stdin).
+pub struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R) -> Self {
+        CountingReader { inner, count: 0 }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+/// Writer counting the bytes written, to measure outputs that are not files (stdout).
+pub struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W> CountingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        CountingWriter { inner, count: 0 }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
 
 fn calculate_size_reduction(original_size: u64, minified_size: u64) -> f64 {
@@ -12,15 +67,98 @@ fn calculate_size_reduction(original_size: u64, minified_size: u64) -> f64 {
     (reduction / original_size as f64) * 100.0
 }
 
-pub fn calculate_and_display_size_reduction(input_path: &str, output_path: &Path) {
-    let original_size = get_file_size(input_path).unwrap();
-    let minified_size = get_file_size(&output_path.to_string_lossy()).unwrap();
-    let original_size_formatted = Size::from_bytes(original_size).to_string();
-    let minified_size_formatted = Size::from_bytes(minified_size).to_string();
+/// Display the size reduction of every processed file on `output`.
+/// With several files, a table with one row per file and a total is displayed.
+pub fn calculate_and_display_size_reduction<W: Write>(
+    files: &[FileSizes],
+    output: &mut W,
+) -> io::Result<()> {
+    if let [file] = files {
+        let reduction_percentage = calculate_size_reduction(file.original_size, file.minified_size);
+        return writeln!(
+            output,
+            "File size reduced by {:.2}% (from {} to {})",
+            reduction_percentage,
+            Size::from_bytes(file.original_size),
+            Size::from_bytes(file.minified_size)
+        );
+    }
+
+    // Padding counts characters, not bytes
+    let total_label = format!("Total ({} files)", files.len());
+    let width = files
+        .iter()
+        .map(|file| file.name.as_str())
+        .chain(["File", total_label.as_str()])
+        .map(|label| label.chars().count())
+        .max()
+        .unwrap_or_default();
+    let mut write_row = |name: &str, original: String, minified: String, reduction: String| {
+        writeln!(
+            output,
+            "{:<width$}  {:>12}  {:>12}  {:>9}",
+            name, original, minified, reduction
+        )
+    };
+    write_row(
+        "File",
+        "Original".to_string(),
+        "Minified".to_string(),
+        "Reduction".to_string(),
+    )?;
+    for file in files {
+        let reduction_percentage = calculate_size_reduction(file.original_size, file.minified_size);
+        write_row(
+            &file.name,
+            Size::from_bytes(file.original_size).to_string(),
+            Size::from_bytes(file.minified_size).to_string(),
+            format!("{:.2}%", reduction_percentage),
+        )?;
+    }
+
+    let total_original: u64 = files.iter().map(|file| file.original_size).sum();
+    let total_minified: u64 = files.iter().map(|file| file.minified_size).sum();
+    write_row(
+        &total_label,
+        Size::from_bytes(total_original).to_string(),
+        Size::from_bytes(total_minified).to_string(),
+        format!(
+            "{:.2}%",
+            calculate_size_reduction(total_original, total_minified)
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_table_columns_are_aligned() {
+        let files = [
+            FileSizes {
+                name: "a.geojson".to_string(),
+                original_size: 2000,
+                minified_size: 1000,
+            },
+            FileSizes {
+                name: "b.geojson".to_string(),
+                original_size: 4000,
+                minified_size: 1000,
+            },
+        ];
+        let mut output = Vec::new();
+
+        calculate_and_display_size_reduction(&files, &mut output).unwrap();
 
-    let reduction_percentage = calculate_size_reduction(original_size, minified_size);
-    println!(
-        "File size reduced by {:.2}% (from {} to {})",
-        reduction_percentage, original_size_formatted, minified_size_formatted
-    );
+        let table = String::from_utf8(output).unwrap();
+        let lengths: Vec<usize> = table.lines().map(|line| line.chars().count()).collect();
+        assert_eq!(lengths.len(), 4);
+        assert!(
+            lengths.iter().all(|length| *length == lengths[0]),
+            "{}",
+            table
+        );
+        assert!(table.lines().last().unwrap().starts_with("Total (2 files)"));
+    }
 }
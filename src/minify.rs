@@ -0,0 +1,499 @@
+use crate::args::Args;
+use crate::clip::{Mask, SpatialFilter};
+use crate::file_operations::{
+    output_format, parse_json_content, process_geojson, simplification_from_args,
+    write_geojson_file, MyError, OutputFormat,
+};
+use crate::geo_operations::{
+    AreaThreshold, BboxMode, Precision, Rounding, Simplification, WindingOrder,
+};
+use crate::pipeline::{Step, Transform};
+use crate::projection::Crs;
+use crate::topojson::geojson_to_topojson;
+use log::info;
+use sonic_rs::Value;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// Everything that can be done to a GeoJSON document, independently of the command line.
+///
+/// ```
+/// use mini_geojson::{minify_str, MinifyOptions};
+///
+/// let options = MinifyOptions::builder()
+///     .decimal(2)
+///     .remove_null_properties(true)
+///     .build();
+/// let minified = minify_str(
+///     r#"{"type": "Feature", "properties": {"a": null}, "geometry": {"type": "Point", "coordinates": [1.2345, 6.789]}}"#,
+///     &options,
+/// )
+/// .unwrap();
+/// assert_eq!(
+///     minified,
+///     r#"{"type":"Feature","properties":{},"geometry":{"type":"Point","coordinates":[1.23,6.79]}}"#
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct MinifyOptions {
+    pub decimal: Option<usize>,
+    /// Round the coordinates to a ground resolution or significant digits instead of `decimal`.
+    pub precision: Option<Precision>,
+    /// Decimals of the elevations (Z), instead of `decimal` or `precision`.
+    pub z_decimal: Option<usize>,
+    /// Decimals of the measures (M), instead of `decimal` or `precision`.
+    pub m_decimal: Option<usize>,
+    /// Drop the elevations and measures, keeping 2D positions.
+    pub force_2d: bool,
+    pub remove_null_properties: bool,
+    pub properties_to_remove: Option<Vec<String>>,
+    pub properties_to_keep: Option<Vec<String>>,
+    pub simplification: Option<Simplification>,
+    pub preserve_topology: bool,
+    /// Repair the geometries, before the other steps and again after truncation.
+    pub fix: bool,
+    /// Intersect the geometries with this mask, in the output CRS, removing the features left empty.
+    pub clip: Option<Arc<Mask>>,
+    /// Keep only the features matching all these filters, after the other steps.
+    pub spatial_filters: Vec<SpatialFilter>,
+    /// Remove the MultiPolygon parts smaller than this, the largest one excepted.
+    pub min_part_area: Option<AreaThreshold>,
+    pub min_hole_area: Option<AreaThreshold>,
+    /// Remove the features whose polygons cover fewer square meters.
+    pub min_feature_area: Option<f64>,
+    /// Cut the geometries crossing the antimeridian into Multi* parts.
+    pub split_antimeridian: bool,
+    /// Remove the duplicate and collinear vertices after rounding,
+    /// with this angle tolerance in degrees (0 for strictly collinear ones).
+    pub clean: Option<f64>,
+    /// Orient the polygon rings, after the other geometry steps.
+    pub rewind: Option<WindingOrder>,
+    pub bbox: BboxMode,
+    /// CRS of the input, read from its legacy `crs` member when `None` (WGS84 without one).
+    pub from_crs: Option<Crs>,
+    /// CRS of the output, WGS84 as RFC 7946 requires unless asked otherwise.
+    pub to_crs: Crs,
+    /// Number of threads processing the features, 0 means one per CPU core.
+    pub jobs: usize,
+    pub format: OutputFormat,
+    pub pretty: bool,
+    /// Name of the TopoJSON object holding the features.
+    pub object_name: String,
+    pub quantization: u32,
+    /// Order of the built-in steps, [`Step::DEFAULT_ORDER`] when `None`.
+    pub steps: Option<Vec<Step>>,
+    /// Custom transforms, run on each feature after the built-in steps.
+    pub transforms: Vec<Arc<dyn Transform>>,
+}
+
+impl Default for MinifyOptions {
+    fn default() -> Self {
+        MinifyOptions {
+            decimal: None,
+            precision: None,
+            z_decimal: None,
+            m_decimal: None,
+            force_2d: false,
+            remove_null_properties: false,
+            properties_to_remove: None,
+            properties_to_keep: None,
+            simplification: None,
+            preserve_topology: false,
+            fix: false,
+            clip: None,
+            spatial_filters: Vec::new(),
+            min_part_area: None,
+            min_hole_area: None,
+            min_feature_area: None,
+            split_antimeridian: false,
+            clean: None,
+            rewind: None,
+            bbox: BboxMode::Keep,
+            from_crs: None,
+            to_crs: Crs::Wgs84,
+            jobs: 1,
+            format: OutputFormat::GeoJson,
+            pretty: false,
+            object_name: "collection".to_string(),
+            quantization: 10_000,
+            steps: None,
+            transforms: Vec::new(),
+        }
+    }
+}
+
+impl MinifyOptions {
+    pub fn builder() -> MinifyOptionsBuilder {
+        MinifyOptionsBuilder::default()
+    }
+
+    /// How the coordinates, and the bboxes that bound them, are rounded.
+    pub fn rounding(&self) -> Rounding {
+        Rounding {
+            decimal: self.decimal,
+            precision: self.precision,
+            z_decimal: self.z_decimal,
+            m_decimal: self.m_decimal,
+            force_2d: self.force_2d,
+            geographic: self.to_crs.is_geographic(),
+        }
+    }
+}
+
+impl From<&Args> for MinifyOptions {
+    fn from(args: &Args) -> Self {
+        let precision = match (args.precision_meters, args.significant_digits) {
+            (Some(meters), _) => Some(Precision::Meters(meters)),
+            (None, Some(digits)) => Some(Precision::SignificantDigits(digits)),
+            (None, None) => None,
+        };
+        MinifyOptions {
+            decimal: args.decimal,
+            precision,
+            z_decimal: args.z_decimal,
+            m_decimal: args.m_decimal,
+            force_2d: args.force_2d,
+            remove_null_properties: args.remove_null_properties,
+            properties_to_remove: args.properties_to_remove.clone(),
+            properties_to_keep: args.properties_to_keep.clone(),
+            simplification: simplification_from_args(args),
+            preserve_topology: args.preserve_topology,
+            fix: args.fix,
+            // The mask files are read by the caller, which can report a failure
+            clip: args.clip_bbox.map(|bbox| Arc::new(Mask::from_bbox(bbox.0))),
+            spatial_filters: args
+                .bbox_filter
+                .map(|bbox| SpatialFilter::Bbox(bbox.0))
+                .into_iter()
+                .collect(),
+            min_part_area: args.min_part_area,
+            min_hole_area: args.min_hole_area,
+            min_feature_area: args.min_feature_area,
+            split_antimeridian: args.split_antimeridian,
+            clean: args.clean,
+            rewind: args.rewind,
+            bbox: args.bbox,
+            from_crs: args.from_crs,
+            to_crs: args.to_crs,
+            jobs: args.jobs,
+            format: output_format(args),
+            pretty: args.pretty,
+            quantization: args.quantization,
+            steps: args.step.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Builder of [`MinifyOptions`], every option is off by default.
+#[derive(Debug, Clone, Default)]
+pub struct MinifyOptionsBuilder {
+    options: MinifyOptions,
+}
+
+impl MinifyOptionsBuilder {
+    /// Number of decimals kept in the coordinates.
+    pub fn decimal(mut self, decimal: usize) -> Self {
+        self.options.decimal = Some(decimal);
+        self
+    }
+
+    /// Round the coordinates to a ground resolution or significant digits instead of decimals.
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.options.precision = Some(precision);
+        self
+    }
+
+    /// Number of decimals kept in the elevations (Z).
+    pub fn z_decimal(mut self, decimal: usize) -> Self {
+        self.options.z_decimal = Some(decimal);
+        self
+    }
+
+    /// Number of decimals kept in the measures (M).
+    pub fn m_decimal(mut self, decimal: usize) -> Self {
+        self.options.m_decimal = Some(decimal);
+        self
+    }
+
+    /// Drop the elevations and measures of the positions.
+    pub fn force_2d(mut self, force_2d: bool) -> Self {
+        self.options.force_2d = force_2d;
+        self
+    }
+
+    /// Remove the properties that are null or empty strings.
+    pub fn remove_null_properties(mut self, remove: bool) -> Self {
+        self.options.remove_null_properties = remove;
+        self
+    }
+
+    pub fn properties_to_remove<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.properties_to_remove = Some(keys.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn properties_to_keep<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.properties_to_keep = Some(keys.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn simplification(mut self, simplification: Simplification) -> Self {
+        self.options.simplification = Some(simplification);
+        self
+    }
+
+    /// Simplify the borders shared by several features only once.
+    pub fn preserve_topology(mut self, preserve: bool) -> Self {
+        self.options.preserve_topology = preserve;
+        self
+    }
+
+    /// Repair the common defects of the geometries, see [`crate::geo_operations::fix_geometry`].
+    pub fn fix(mut self, fix: bool) -> Self {
+        self.options.fix = fix;
+        self
+    }
+
+    /// Remove the MultiPolygon parts smaller than `threshold`, see
+    /// [`crate::geo_operations::remove_small_parts`].
+    pub fn min_part_area(mut self, threshold: AreaThreshold) -> Self {
+        self.options.min_part_area = Some(threshold);
+        self
+    }
+
+    /// Remove the holes smaller than `threshold`.
+    pub fn min_hole_area(mut self, threshold: AreaThreshold) -> Self {
+        self.options.min_hole_area = Some(threshold);
+        self
+    }
+
+    /// Intersect the geometries with a mask, see [`crate::clip::clip_geometry`].
+    pub fn clip(mut self, mask: Mask) -> Self {
+        self.options.clip = Some(Arc::new(mask));
+        self
+    }
+
+    /// Keep only the features matching `filter`, see [`SpatialFilter`].
+    pub fn spatial_filter(mut self, filter: SpatialFilter) -> Self {
+        self.options.spatial_filters.push(filter);
+        self
+    }
+
+    /// Remove the features whose polygons cover fewer than `square_meters`.
+    pub fn min_feature_area(mut self, square_meters: f64) -> Self {
+        self.options.min_feature_area = Some(square_meters);
+        self
+    }
+
+    /// Cut the geometries crossing the antimeridian, see
+    /// [`crate::geo_operations::split_antimeridian`].
+    pub fn split_antimeridian(mut self, split: bool) -> Self {
+        self.options.split_antimeridian = split;
+        self
+    }
+
+    /// Remove the redundant vertices, see [`crate::geo_operations::clean_geometry`].
+    pub fn clean(mut self, max_angle: f64) -> Self {
+        self.options.clean = Some(max_angle);
+        self
+    }
+
+    /// Orient the polygon rings, see [`crate::geo_operations::rewind_geometry`].
+    pub fn rewind(mut self, order: WindingOrder) -> Self {
+        self.options.rewind = Some(order);
+        self
+    }
+
+    /// Keep (rounded like the coordinates), compute or strip the bboxes.
+    pub fn bbox(mut self, mode: BboxMode) -> Self {
+        self.options.bbox = mode;
+        self
+    }
+
+    /// CRS of the input, overriding its `crs` member.
+    pub fn from_crs(mut self, crs: Crs) -> Self {
+        self.options.from_crs = Some(crs);
+        self
+    }
+
+    /// Reproject the coordinates to this CRS; a `crs` member names it unless it is WGS84.
+    pub fn to_crs(mut self, crs: Crs) -> Self {
+        self.options.to_crs = crs;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.options.jobs = jobs;
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.options.pretty = pretty;
+        self
+    }
+
+    pub fn object_name(mut self, object_name: impl Into<String>) -> Self {
+        self.options.object_name = object_name.into();
+        self
+    }
+
+    pub fn quantization(mut self, quantization: u32) -> Self {
+        self.options.quantization = quantization;
+        self
+    }
+
+    /// Run the built-in steps in this order instead of [`Step::DEFAULT_ORDER`].
+    pub fn steps(mut self, steps: impl IntoIterator<Item = Step>) -> Self {
+        self.options.steps = Some(steps.into_iter().collect());
+        self
+    }
+
+    /// Add a custom transform, run after the built-in steps and the transforms added before.
+    pub fn transform<T: Transform + 'static>(mut self, transform: T) -> Self {
+        self.options.transforms.push(Arc::new(transform));
+        self
+    }
+
+    pub fn build(self) -> MinifyOptions {
+        self.options
+    }
+}
+
+/// Minify a parsed GeoJSON document and return the output document
+/// (a TopoJSON topology with [`OutputFormat::TopoJson`]).
+pub fn minify_value(mut geojson: Value, options: &MinifyOptions) -> Result<Value, MyError> {
+    process_geojson(&mut geojson, options)?;
+    Ok(match options.format {
+        OutputFormat::GeoJson => geojson,
+        OutputFormat::TopoJson => {
+            geojson_to_topojson(&geojson, &options.object_name, options.quantization)
+        }
+    })
+}
+
+/// Minify a GeoJSON (or TopoJSON) string.
+pub fn minify_str(input: &str, options: &MinifyOptions) -> Result<String, MyError> {
+    let mut output = Vec::new();
+    minify_reader_to_writer(input.as_bytes(), &mut output, options)?;
+    // Serialized JSON is always valid UTF-8
+    Ok(String::from_utf8(output).unwrap_or_default())
+}
+
+/// Read a whole GeoJSON (or TopoJSON) document from `reader` and write it minified to `writer`.
+pub fn minify_reader_to_writer<R: Read, W: Write>(
+    mut reader: R,
+    writer: W,
+    options: &MinifyOptions,
+) -> Result<(), MyError> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+    let geojson = parse_json_content(&content, "input")?;
+    drop(content);
+
+    let output = minify_value(geojson, options)?;
+    info!("GeoJSON processed successfully.");
+    write_geojson_file(&output, writer, options.pretty)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_operations::read_json_file;
+    use crate::topojson::is_topojson;
+    use sonic_rs::{from_str, json, JsonValueTrait};
+
+    #[test]
+    fn test_decimal_does_not_depend_on_other_options() {
+        use clap::Parser;
+        let options = |extra: &[&str]| {
+            let args = Args::parse_from(
+                ["mini-geojson", "-i", "in.geojson", "-d", "2"]
+                    .iter()
+                    .chain(extra),
+            );
+            MinifyOptions::from(&args).decimal
+        };
+        assert_eq!(options(&[]), Some(2));
+        assert_eq!(options(&["-R"]), Some(2));
+        assert_eq!(options(&["--step", "truncate"]), Some(2));
+        assert_eq!(options(&["--z-decimal", "0"]), Some(2));
+    }
+
+    #[test]
+    fn test_kept_bboxes_are_rounded_like_the_coordinates() {
+        let input = r#"{"type":"FeatureCollection","bbox":[1.23456,2.34567,1.23456,2.34567],
+            "features":[{"type":"Feature","bbox":[1.23456,2.34567,1.23456,2.34567],
+            "properties":{},"geometry":{"type":"Point","coordinates":[1.23456,2.34567]}}]}"#;
+        let options = MinifyOptions::builder()
+            .precision(Precision::SignificantDigits(3))
+            .build();
+
+        let output: Value = from_str(&minify_str(input, &options).unwrap()).unwrap();
+
+        let rounded = json!([1.23, 2.35, 1.23, 2.35]);
+        assert_eq!(output["bbox"], rounded);
+        assert_eq!(output["features"][0]["bbox"], rounded);
+        assert_eq!(
+            output["features"][0]["geometry"]["coordinates"],
+            json!([1.23, 2.35])
+        );
+    }
+
+    #[test]
+    fn test_minify_str_removes_properties() {
+        let options = MinifyOptions::builder()
+            .properties_to_remove(["name", "key_to_delete"])
+            .build();
+        let input = std::fs::read_to_string("data/small-departements.geojson").unwrap();
+
+        let minified = minify_str(&input, &options).unwrap();
+
+        let expected =
+            read_json_file("data/min_small-departements-keys-to-delete.geojson").unwrap();
+        assert_eq!(from_str::<Value>(&minified).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_minify_value_to_topojson() {
+        let options = MinifyOptions::builder()
+            .format(OutputFormat::TopoJson)
+            .object_name("departements")
+            .build();
+        let geojson = read_json_file("data/small-departements.geojson").unwrap();
+
+        let topojson = minify_value(geojson, &options).unwrap();
+
+        assert!(is_topojson(&topojson));
+        assert!(topojson["objects"]["departements"].is_object());
+    }
+
+    #[test]
+    fn test_minify_reader_to_writer_pretty() {
+        let options = MinifyOptions::builder().pretty(true).build();
+        let mut output = Vec::new();
+
+        minify_reader_to_writer(
+            r#"{"type":"FeatureCollection","features":[]}"#.as_bytes(),
+            &mut output,
+            &options,
+        )
+        .unwrap();
+
+        assert!(String::from_utf8(output).unwrap().contains('\n'));
+    }
+}
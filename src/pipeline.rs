@@ -0,0 +1,708 @@
+use crate::clip::{clip_geometry, Mask};
+use crate::geo_operations::{
+    bbox_to_2d, clean_geometry, compute_geometry_bbox, fix_geometry, geometry_area,
+    keep_properties, remove_null_or_empty_properties, remove_properties, remove_small_parts,
+    reproject_geometry, rewind_geometry, round_geometry, simplify_geometry, split_antimeridian,
+    truncate_geometry, update_bbox, update_geometry_bboxes, AreaThreshold, BboxMode, FixSummary,
+    Rounding, Simplification, WindingOrder,
+};
+use crate::minify::MinifyOptions;
+use crate::projection::Reprojection;
+use crate::topology::simplify_geometries_with_topology;
+use clap::ValueEnum;
+use log::{debug, warn};
+use sonic_rs::{JsonValueMutTrait, JsonValueTrait, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// A step applied to every feature of a document.
+/// Features may be processed on several threads, hence `Send + Sync`.
+pub trait Transform: Send + Sync {
+    /// Name of the step, shown in the logs.
+    fn name(&self) -> &str;
+
+    /// Change the feature in place; replacing it with null removes it from its collection.
+    fn apply(&self, feature: &mut Value);
+
+    /// Same as [`Transform::apply`], for the feature at `index` in its collection.
+    /// Transforms that report per feature override it.
+    fn apply_at(&self, index: usize, feature: &mut Value) {
+        let _ = index;
+        self.apply(feature);
+    }
+}
+
+impl fmt::Debug for dyn Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Built-in steps, in the order they run when no order is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Step {
+    Reproject,
+    Fix,
+    Clip,
+    MinArea,
+    Simplify,
+    Antimeridian,
+    Truncate,
+    Clean,
+    Rewind,
+    Bbox,
+    RemoveNulls,
+    RemoveProperties,
+    KeepProperties,
+}
+
+impl Step {
+    /// The other steps work on the output coordinates, so reprojection comes first.
+    /// Clipping needs valid rings and may leave slivers, so it runs between the repair
+    /// and the removal of small parts.
+    /// Small parts are removed before simplification, which then has fewer vertices to process.
+    /// Truncation often creates duplicate and collinear vertices, so the cleanup
+    /// and the repair run after it.
+    pub const DEFAULT_ORDER: [Step; 14] = [
+        Step::Reproject,
+        Step::Fix,
+        Step::Clip,
+        Step::MinArea,
+        Step::Simplify,
+        Step::Antimeridian,
+        Step::Truncate,
+        Step::Clean,
+        Step::Fix,
+        Step::Rewind,
+        Step::Bbox,
+        Step::RemoveNulls,
+        Step::RemoveProperties,
+        Step::KeepProperties,
+    ];
+}
+
+/// Changes made by [`Fix`], by feature index.
+pub type FixReport = BTreeMap<usize, FixSummary>;
+
+/// Repair the geometry of each feature, see [`fix_geometry`].
+pub struct Fix {
+    report: Arc<Mutex<FixReport>>,
+    geographic: bool,
+}
+
+impl Default for Fix {
+    fn default() -> Self {
+        Fix::new(Arc::default(), true)
+    }
+}
+
+impl Fix {
+    /// A repair step recording its changes in `report`,
+    /// for `geographic` coordinates or projected ones.
+    pub fn new(report: Arc<Mutex<FixReport>>, geographic: bool) -> Self {
+        Fix { report, geographic }
+    }
+}
+
+impl Transform for Fix {
+    fn name(&self) -> &str {
+        "fix"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        if let Some(geometry) = feature.get_mut("geometry") {
+            fix_geometry(geometry, self.geographic);
+        }
+    }
+
+    fn apply_at(&self, index: usize, feature: &mut Value) {
+        let Some(geometry) = feature.get_mut("geometry") else {
+            return;
+        };
+        let summary = fix_geometry(geometry, self.geographic);
+        if !summary.is_empty() {
+            if let Ok(mut report) = self.report.lock() {
+                report.entry(index).or_default().add(&summary);
+            }
+        }
+    }
+}
+
+/// Convert the coordinates to another CRS, see [`reproject_geometry`].
+/// The bboxes already present are recomputed in the new CRS.
+pub struct Reproject(pub Reprojection);
+
+impl Transform for Reproject {
+    fn name(&self) -> &str {
+        "reproject"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        if let Some(geometry) = feature.get_mut("geometry") {
+            reproject_geometry(geometry, &self.0);
+        }
+        recompute_bboxes(feature);
+    }
+}
+
+/// Recompute the bboxes present in a feature whose coordinates have changed.
+fn recompute_bboxes(feature: &mut Value) {
+    if let Some(geometry) = feature.get_mut("geometry") {
+        update_geometry_bboxes(geometry, BboxMode::Compute, &Rounding::default());
+    }
+    if feature.get("bbox").is_some() {
+        update_bbox(
+            feature,
+            BboxMode::Compute,
+            &Rounding::default(),
+            |feature| feature.get("geometry").and_then(compute_geometry_bbox),
+        );
+    }
+}
+
+/// Intersect the geometries with a mask, see [`clip_geometry`].
+/// The features left without geometry are removed, the bboxes present are recomputed.
+pub struct Clip(pub Arc<Mask>);
+
+impl Transform for Clip {
+    fn name(&self) -> &str {
+        "clip"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        let Some(geometry) = feature.get_mut("geometry") else {
+            return;
+        };
+        if geometry.is_null() {
+            return;
+        }
+        if !clip_geometry(geometry, &self.0) {
+            debug!("Removing a feature outside of the clip mask");
+            *feature = Value::new();
+            return;
+        }
+        recompute_bboxes(feature);
+    }
+}
+
+/// Remove the small polygon parts and holes, see [`remove_small_parts`],
+/// and the features whose polygons cover less than `min_feature` square meters.
+pub struct MinArea {
+    pub min_part: Option<AreaThreshold>,
+    pub min_hole: Option<AreaThreshold>,
+    pub min_feature: Option<f64>,
+    /// Whether the coordinates are longitudes and latitudes.
+    pub geographic: bool,
+}
+
+impl Transform for MinArea {
+    fn name(&self) -> &str {
+        "min-area"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        let Some(geometry) = feature.get_mut("geometry") else {
+            return;
+        };
+        if let Some(min_feature) = self.min_feature {
+            if geometry_area(geometry, self.geographic).is_some_and(|area| area < min_feature) {
+                debug!("Removing a feature smaller than {} m²", min_feature);
+                *feature = Value::new();
+                return;
+            }
+        }
+        remove_small_parts(geometry, self.min_part, self.min_hole, self.geographic);
+    }
+}
+
+/// Cut the geometries crossing the antimeridian, see [`split_antimeridian`].
+pub struct Antimeridian;
+
+impl Transform for Antimeridian {
+    fn name(&self) -> &str {
+        "antimeridian"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        if let Some(geometry) = feature.get_mut("geometry") {
+            split_antimeridian(geometry);
+        }
+    }
+}
+
+/// Orient the polygon rings, see [`rewind_geometry`].
+pub struct Rewind(pub WindingOrder);
+
+impl Transform for Rewind {
+    fn name(&self) -> &str {
+        "rewind"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        if let Some(geometry) = feature.get_mut("geometry") {
+            rewind_geometry(geometry, self.0);
+        }
+    }
+}
+
+/// Keep, compute or strip the bboxes of each feature and its geometry, rounded with `rounding`.
+pub struct Bbox {
+    pub mode: BboxMode,
+    pub rounding: Rounding,
+}
+
+impl Transform for Bbox {
+    fn name(&self) -> &str {
+        "bbox"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        if let Some(geometry) = feature.get_mut("geometry") {
+            update_geometry_bboxes(geometry, self.mode, &self.rounding);
+        }
+        update_bbox(feature, self.mode, &self.rounding, |feature| {
+            feature.get("geometry").and_then(compute_geometry_bbox)
+        });
+    }
+}
+
+pub struct Simplify(pub Simplification);
+
+impl Transform for Simplify {
+    fn name(&self) -> &str {
+        "simplify"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        if let Some(geometry) = feature.get_mut("geometry") {
+            simplify_geometry(geometry, &self.0);
+        }
+    }
+}
+
+/// Simplify the borders shared by the features only once, see
+/// [`simplify_geometries_with_topology`].
+/// The features of a collection must be simplified together: [`Pipeline::split_at`]
+/// gives the steps to run before and after it. Applied to a single feature,
+/// the borders shared by its own parts are kept.
+pub struct SimplifyTopology(pub Simplification);
+
+impl SimplifyTopology {
+    pub const NAME: &'static str = "simplify-topology";
+}
+
+impl Transform for SimplifyTopology {
+    fn name(&self) -> &str {
+        SimplifyTopology::NAME
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        if let Some(geometry) = feature.get_mut("geometry") {
+            simplify_geometries_with_topology(&mut [geometry], &self.0);
+        }
+    }
+}
+
+/// Keep this number of decimals in the coordinates.
+pub struct Truncate(pub usize);
+
+impl Transform for Truncate {
+    fn name(&self) -> &str {
+        "truncate"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        if let Some(geometry) = feature.get_mut("geometry") {
+            truncate_geometry(geometry, self.0);
+        }
+    }
+}
+
+/// Round each ordinate with its own precision and drop the extra dimensions,
+/// see [`round_geometry`].
+pub struct Round(pub Rounding);
+
+impl Transform for Round {
+    fn name(&self) -> &str {
+        "round"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        if let Some(geometry) = feature.get_mut("geometry") {
+            round_geometry(geometry, &self.0);
+        }
+        if self.0.force_2d {
+            bbox_to_2d(feature);
+        }
+    }
+}
+
+/// Remove the duplicate and collinear vertices, turning by at most this angle in degrees,
+/// see [`clean_geometry`].
+pub struct Clean(pub f64);
+
+impl Transform for Clean {
+    fn name(&self) -> &str {
+        "clean"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        if let Some(geometry) = feature.get_mut("geometry") {
+            clean_geometry(geometry, self.0);
+        }
+    }
+}
+
+/// Remove the properties that are null or empty strings.
+pub struct RemoveNullProperties;
+
+impl Transform for RemoveNullProperties {
+    fn name(&self) -> &str {
+        "remove-nulls"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        remove_null_or_empty_properties(feature);
+    }
+}
+
+pub struct RemoveProperties(pub Vec<String>);
+
+impl Transform for RemoveProperties {
+    fn name(&self) -> &str {
+        "remove-properties"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        remove_properties(feature, &self.0);
+    }
+}
+
+pub struct KeepProperties(pub Vec<String>);
+
+impl Transform for KeepProperties {
+    fn name(&self) -> &str {
+        "keep-properties"
+    }
+
+    fn apply(&self, feature: &mut Value) {
+        keep_properties(feature, &self.0);
+    }
+}
+
+/// Ordered list of transforms run on each feature.
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    transforms: Vec<Arc<dyn Transform>>,
+    fixes: Arc<Mutex<FixReport>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    /// Append a transform, run after the ones already in the pipeline.
+    pub fn with<T: Transform + 'static>(mut self, transform: T) -> Self {
+        self.transforms.push(Arc::new(transform));
+        self
+    }
+
+    pub fn push(&mut self, transform: Arc<dyn Transform>) {
+        self.transforms.push(transform);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.transforms.iter().map(|t| t.name()).collect()
+    }
+
+    pub fn apply(&self, feature: &mut Value) {
+        for transform in &self.transforms {
+            transform.apply(feature);
+        }
+    }
+
+    /// Apply the transforms to the feature at `index` in its collection.
+    pub fn apply_at(&self, index: usize, feature: &mut Value) {
+        for transform in &self.transforms {
+            transform.apply_at(index, feature);
+        }
+    }
+
+    /// The transforms before and after the first one named `name`, which is left out,
+    /// or `None` without such a transform. Both parts share the repair report.
+    pub fn split_at(&self, name: &str) -> Option<(Pipeline, Pipeline)> {
+        let position = self.transforms.iter().position(|t| t.name() == name)?;
+        let part = |transforms: &[Arc<dyn Transform>]| Pipeline {
+            transforms: transforms.to_vec(),
+            fixes: self.fixes.clone(),
+        };
+        Some((
+            part(&self.transforms[..position]),
+            part(&self.transforms[position + 1..]),
+        ))
+    }
+
+    /// Changes made by the repair steps built from [`MinifyOptions`], by feature index.
+    pub fn fixes(&self) -> FixReport {
+        self.fixes
+            .lock()
+            .map(|fixes| fixes.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// The built-in steps enabled by `options`, in `options.steps` order,
+/// followed by the custom transforms of `options.transforms`.
+impl From<&MinifyOptions> for Pipeline {
+    fn from(options: &MinifyOptions) -> Self {
+        let explicit = options.steps.is_some();
+        let steps = options.steps.as_deref().unwrap_or(&Step::DEFAULT_ORDER);
+        let mut pipeline = Pipeline::new();
+        for step in steps {
+            let transform: Option<Arc<dyn Transform>> = match step {
+                Step::Reproject => {
+                    options
+                        .from_crs
+                        .filter(|from| *from != options.to_crs)
+                        .map(|from| {
+                            Arc::new(Reproject(Reprojection::new(from, options.to_crs)))
+                                as Arc<dyn Transform>
+                        })
+                }
+                Step::Fix => options.fix.then(|| {
+                    Arc::new(Fix::new(
+                        pipeline.fixes.clone(),
+                        options.to_crs.is_geographic(),
+                    )) as Arc<dyn Transform>
+                }),
+                Step::Clip => options
+                    .clip
+                    .clone()
+                    .map(|mask| Arc::new(Clip(mask)) as Arc<dyn Transform>),
+                Step::MinArea => (options.min_part_area.is_some()
+                    || options.min_hole_area.is_some()
+                    || options.min_feature_area.is_some())
+                .then(|| {
+                    Arc::new(MinArea {
+                        min_part: options.min_part_area,
+                        min_hole: options.min_hole_area,
+                        min_feature: options.min_feature_area,
+                        geographic: options.to_crs.is_geographic(),
+                    }) as Arc<dyn Transform>
+                }),
+                Step::Simplify => options.simplification.map(|s| {
+                    let simplification = s.for_crs(options.to_crs);
+                    if options.preserve_topology {
+                        Arc::new(SimplifyTopology(simplification)) as Arc<dyn Transform>
+                    } else {
+                        Arc::new(Simplify(simplification)) as Arc<dyn Transform>
+                    }
+                }),
+                // Plain decimals apply to every ordinate, the other settings need a rounding
+                Step::Truncate => {
+                    let rounding = options.rounding();
+                    if rounding.precision.is_some()
+                        || rounding.z_decimal.is_some()
+                        || rounding.m_decimal.is_some()
+                        || rounding.force_2d
+                    {
+                        Some(Arc::new(Round(rounding)) as Arc<dyn Transform>)
+                    } else {
+                        options
+                            .decimal
+                            .map(|d| Arc::new(Truncate(d)) as Arc<dyn Transform>)
+                    }
+                }
+                // Projected coordinates have no antimeridian to cut at
+                Step::Antimeridian => (options.split_antimeridian
+                    && options.to_crs.is_geographic())
+                .then(|| Arc::new(Antimeridian) as Arc<dyn Transform>),
+                Step::Clean => options
+                    .clean
+                    .map(|angle| Arc::new(Clean(angle)) as Arc<dyn Transform>),
+                Step::Rewind => options
+                    .rewind
+                    .map(|order| Arc::new(Rewind(order)) as Arc<dyn Transform>),
+                // Kept bboxes only change when the coordinates are rounded
+                Step::Bbox => {
+                    let rounding = options.rounding();
+                    (options.bbox != BboxMode::Keep
+                        || rounding.decimal.is_some()
+                        || rounding.precision.is_some()
+                        || rounding.z_decimal.is_some())
+                    .then(|| {
+                        Arc::new(Bbox {
+                            mode: options.bbox,
+                            rounding,
+                        }) as Arc<dyn Transform>
+                    })
+                }
+                Step::RemoveNulls => options
+                    .remove_null_properties
+                    .then(|| Arc::new(RemoveNullProperties) as Arc<dyn Transform>),
+                Step::RemoveProperties => options
+                    .properties_to_remove
+                    .clone()
+                    .map(|keys| Arc::new(RemoveProperties(keys)) as Arc<dyn Transform>),
+                Step::KeepProperties => options
+                    .properties_to_keep
+                    .clone()
+                    .map(|keys| Arc::new(KeepProperties(keys)) as Arc<dyn Transform>),
+            };
+            match transform {
+                Some(transform) => pipeline.push(transform),
+                None if explicit => warn!("Step {:?} is skipped: its option is not set", step),
+                None => {}
+            }
+        }
+        for transform in &options.transforms {
+            pipeline.push(transform.clone());
+        }
+        debug!("Pipeline: {:?}", pipeline.names());
+        pipeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo_operations::ToleranceUnit;
+    use crate::projection::Crs;
+    use sonic_rs::{json, JsonContainerTrait};
+
+    struct Rename;
+
+    impl Transform for Rename {
+        fn name(&self) -> &str {
+            "rename"
+        }
+
+        fn apply(&self, feature: &mut Value) {
+            if let Some(properties) = feature
+                .get_mut("properties")
+                .and_then(|p| p.as_object_mut())
+            {
+                if let Some(value) = properties.remove(&"nom") {
+                    properties.insert("name", value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pipeline_runs_steps_in_the_given_order() {
+        let feature = json!({
+            "type": "Feature",
+            "properties": {"nom": "Ain", "code": "01"},
+            "geometry": {"type": "Point", "coordinates": [5.34567, 46.12345]}
+        });
+
+        let keep_then_rename = MinifyOptions::builder()
+            .decimal(2)
+            .properties_to_keep(["name"])
+            .steps([Step::KeepProperties, Step::Truncate])
+            .transform(Rename)
+            .build();
+        let mut first = feature.clone();
+        Pipeline::from(&keep_then_rename).apply(&mut first);
+        assert_eq!(first["properties"], json!({}));
+        assert_eq!(first["geometry"]["coordinates"], json!([5.35, 46.12]));
+
+        let rename_then_keep = Pipeline::new()
+            .with(Rename)
+            .with(KeepProperties(vec!["name".to_string()]));
+        let mut second = feature;
+        rename_then_keep.apply(&mut second);
+        assert_eq!(second["properties"], json!({"name": "Ain"}));
+    }
+
+    #[test]
+    fn test_pipeline_reports_fixes_by_feature_index() {
+        let options = MinifyOptions::builder().fix(true).decimal(1).build();
+        let pipeline = Pipeline::from(&options);
+        let mut valid = json!({
+            "type": "Feature",
+            "geometry": {"type": "LineString", "coordinates": [[0.0, 0.0], [1.0, 1.0]]}
+        });
+        let mut duplicated = json!({
+            "type": "Feature",
+            "geometry": {"type": "LineString", "coordinates": [[0.0, 0.0], [0.01, 0.01], [1.0, 1.0]]}
+        });
+
+        pipeline.apply_at(0, &mut valid);
+        pipeline.apply_at(1, &mut duplicated);
+
+        assert_eq!(pipeline.names(), vec!["fix", "truncate", "fix", "bbox"]);
+        assert_eq!(
+            duplicated["geometry"]["coordinates"],
+            json!([[0.0, 0.0], [1.0, 1.0]])
+        );
+        let fixes = pipeline.fixes();
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(fixes[&1].removed_duplicate_vertices, 1);
+    }
+
+    #[test]
+    fn test_pipeline_fixes_projected_coordinates_without_clamping() {
+        let options = MinifyOptions::builder()
+            .from_crs(Crs::Wgs84)
+            .to_crs(Crs::Lambert93)
+            .fix(true)
+            .build();
+        let pipeline = Pipeline::from(&options);
+        let mut feature = json!({
+            "type": "Feature",
+            "geometry": {"type": "LineString", "coordinates": [[3.0, 46.5], [3.0, 46.5], [4.0, 47.0]]}
+        });
+
+        pipeline.apply(&mut feature);
+
+        assert_eq!(pipeline.names(), vec!["reproject", "fix", "fix"]);
+        let line = feature["geometry"]["coordinates"].as_array().unwrap();
+        assert_eq!(line.len(), 2);
+        assert!(line[0][0].as_f64().unwrap() > 600_000.0);
+    }
+
+    #[test]
+    fn test_pipeline_splits_at_the_topology_simplification() {
+        let simplification = Simplification::DouglasPeucker {
+            tolerance: 0.01,
+            unit: ToleranceUnit::Degrees,
+        };
+        let options = MinifyOptions::builder()
+            .fix(true)
+            .min_feature_area(1.0)
+            .simplification(simplification)
+            .preserve_topology(true)
+            .decimal(3)
+            .build();
+        let pipeline = Pipeline::from(&options);
+
+        let (before, after) = pipeline.split_at(SimplifyTopology::NAME).unwrap();
+        assert_eq!(before.names(), vec!["fix", "min-area"]);
+        assert_eq!(after.names(), vec!["truncate", "fix", "bbox"]);
+        assert!(Pipeline::from(
+            &MinifyOptions::builder()
+                .simplification(simplification)
+                .build()
+        )
+        .split_at(SimplifyTopology::NAME)
+        .is_none());
+    }
+
+    #[test]
+    fn test_pipeline_from_options_skips_disabled_steps() {
+        let options = MinifyOptions::builder()
+            .remove_null_properties(true)
+            .build();
+
+        assert_eq!(Pipeline::from(&options).names(), vec!["remove-nulls"]);
+        assert!(Pipeline::from(&MinifyOptions::default()).is_empty());
+    }
+}
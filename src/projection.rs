@@ -0,0 +1,281 @@
+use clap::ValueEnum;
+use log::debug;
+use sonic_rs::{JsonValueTrait, Object, Value};
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+/// Coordinate reference systems that can be converted without PROJ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Crs {
+    /// WGS84 longitude and latitude, the only CRS allowed by RFC 7946.
+    #[value(name = "4326", aliases = ["epsg:4326", "wgs84", "crs84"])]
+    Wgs84,
+    /// Web Mercator, in meters.
+    #[value(name = "3857", aliases = ["epsg:3857", "web-mercator"])]
+    WebMercator,
+    /// Lambert-93, the French official projection (RGF93), in meters.
+    #[value(name = "2154", aliases = ["epsg:2154", "lambert93"])]
+    Lambert93,
+}
+
+/// Semi-major axis of WGS84 and GRS80, in meters.
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+/// Latitude beyond which Web Mercator is not defined.
+const WEB_MERCATOR_MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// Lambert-93 parameters: GRS80 ellipsoid, conic conformal with two standard parallels.
+const GRS80_FLATTENING: f64 = 1.0 / 298.257_222_101;
+const LAMBERT93_PARALLELS: (f64, f64) = (44.0, 49.0);
+const LAMBERT93_ORIGIN: (f64, f64) = (3.0, 46.5);
+const LAMBERT93_FALSE_ORIGIN: (f64, f64) = (700_000.0, 6_600_000.0);
+
+/// Constants of the Lambert conformal conic projection, derived from its parameters.
+#[derive(Debug, Clone)]
+struct LambertConic {
+    e: f64,
+    n: f64,
+    /// Semi-major axis times the F constant.
+    af: f64,
+    rho0: f64,
+    lon0: f64,
+}
+
+impl LambertConic {
+    fn lambert93() -> Self {
+        let f = GRS80_FLATTENING;
+        let e = (2.0 * f - f * f).sqrt();
+        let m = |phi: f64| phi.cos() / (1.0 - (e * phi.sin()).powi(2)).sqrt();
+        let t = |phi: f64| {
+            (FRAC_PI_4 - phi / 2.0).tan()
+                / ((1.0 - e * phi.sin()) / (1.0 + e * phi.sin())).powf(e / 2.0)
+        };
+        let (phi1, phi2) = (
+            LAMBERT93_PARALLELS.0.to_radians(),
+            LAMBERT93_PARALLELS.1.to_radians(),
+        );
+        let n = (m(phi1).ln() - m(phi2).ln()) / (t(phi1).ln() - t(phi2).ln());
+        let af = SEMI_MAJOR_AXIS * m(phi1) / (n * t(phi1).powf(n));
+        LambertConic {
+            e,
+            n,
+            af,
+            rho0: af * t(LAMBERT93_ORIGIN.1.to_radians()).powf(n),
+            lon0: LAMBERT93_ORIGIN.0.to_radians(),
+        }
+    }
+
+    fn t(&self, phi: f64) -> f64 {
+        let e_sin = self.e * phi.sin();
+        (FRAC_PI_4 - phi / 2.0).tan() / ((1.0 - e_sin) / (1.0 + e_sin)).powf(self.e / 2.0)
+    }
+
+    fn forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let rho = self.af * self.t(lat.to_radians()).powf(self.n);
+        let theta = self.n * (lon.to_radians() - self.lon0);
+        (
+            LAMBERT93_FALSE_ORIGIN.0 + rho * theta.sin(),
+            LAMBERT93_FALSE_ORIGIN.1 + self.rho0 - rho * theta.cos(),
+        )
+    }
+
+    fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let dx = x - LAMBERT93_FALSE_ORIGIN.0;
+        let dy = self.rho0 - (y - LAMBERT93_FALSE_ORIGIN.1);
+        let rho = self.n.signum() * dx.hypot(dy);
+        let t = (rho / self.af).powf(1.0 / self.n);
+        let theta = dx.atan2(dy);
+        // The latitude has no closed form, it converges in a few iterations
+        let mut phi = FRAC_PI_2 - 2.0 * t.atan();
+        for _ in 0..10 {
+            let e_sin = self.e * phi.sin();
+            let next =
+                FRAC_PI_2 - 2.0 * (t * ((1.0 - e_sin) / (1.0 + e_sin)).powf(self.e / 2.0)).atan();
+            let converged = (next - phi).abs() < 1e-12;
+            phi = next;
+            if converged {
+                break;
+            }
+        }
+        ((theta / self.n + self.lon0).to_degrees(), phi.to_degrees())
+    }
+}
+
+impl Crs {
+    /// Whether the coordinates are longitudes and latitudes rather than meters.
+    pub fn is_geographic(&self) -> bool {
+        *self == Crs::Wgs84
+    }
+
+    pub fn epsg_code(&self) -> u32 {
+        match self {
+            Crs::Wgs84 => 4326,
+            Crs::WebMercator => 3857,
+            Crs::Lambert93 => 2154,
+        }
+    }
+
+    fn from_epsg_code(code: u32) -> Option<Crs> {
+        match code {
+            4326 => Some(Crs::Wgs84),
+            3857 | 900913 => Some(Crs::WebMercator),
+            2154 => Some(Crs::Lambert93),
+            _ => None,
+        }
+    }
+
+    /// CRS of a legacy `crs` member, such as
+    /// `{"type": "name", "properties": {"name": "urn:ogc:def:crs:EPSG::2154"}}`.
+    pub fn from_crs_member(crs: &Value) -> Option<Crs> {
+        let name = crs.get("properties")?.get("name")?.as_str()?;
+        if name.ends_with("CRS84") {
+            return Some(Crs::Wgs84);
+        }
+        let code = name.rsplit(':').next()?.parse().ok()?;
+        let parsed = Crs::from_epsg_code(code);
+        debug!("CRS member {:?} is {:?}", name, parsed);
+        parsed
+    }
+
+    /// Legacy `crs` member naming this CRS.
+    pub fn crs_member(&self) -> Value {
+        let mut properties = Object::new();
+        properties.insert(
+            "name",
+            format!("urn:ogc:def:crs:EPSG::{}", self.epsg_code()).as_str(),
+        );
+        let mut crs = Object::new();
+        crs.insert("type", "name");
+        crs.insert("properties", properties);
+        crs.into_value()
+    }
+
+    /// Convert a position of this CRS to WGS84 longitude and latitude.
+    pub fn to_wgs84(&self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            Crs::Wgs84 => (x, y),
+            Crs::WebMercator => (
+                (x / SEMI_MAJOR_AXIS).to_degrees(),
+                (2.0 * (y / SEMI_MAJOR_AXIS).exp().atan() - FRAC_PI_2).to_degrees(),
+            ),
+            Crs::Lambert93 => LambertConic::lambert93().inverse(x, y),
+        }
+    }
+
+    /// Convert WGS84 longitude and latitude to a position of this CRS.
+    pub fn from_wgs84(&self, lon: f64, lat: f64) -> (f64, f64) {
+        match self {
+            Crs::Wgs84 => (lon, lat),
+            Crs::WebMercator => {
+                let lat = lat.clamp(-WEB_MERCATOR_MAX_LATITUDE, WEB_MERCATOR_MAX_LATITUDE);
+                (
+                    SEMI_MAJOR_AXIS * lon.to_radians(),
+                    SEMI_MAJOR_AXIS * (FRAC_PI_4 + lat.to_radians() / 2.0).tan().ln(),
+                )
+            }
+            Crs::Lambert93 => LambertConic::lambert93().forward(lon, lat),
+        }
+    }
+}
+
+/// Converts positions from one CRS to another, through WGS84.
+#[derive(Debug, Clone)]
+pub struct Reprojection {
+    from: Crs,
+    to: Crs,
+    lambert93: LambertConic,
+}
+
+impl Reprojection {
+    pub fn new(from: Crs, to: Crs) -> Self {
+        Reprojection {
+            from,
+            to,
+            lambert93: LambertConic::lambert93(),
+        }
+    }
+
+    pub fn from(&self) -> Crs {
+        self.from
+    }
+
+    pub fn to(&self) -> Crs {
+        self.to
+    }
+
+    pub fn convert(&self, x: f64, y: f64) -> (f64, f64) {
+        let (lon, lat) = match self.from {
+            Crs::Lambert93 => self.lambert93.inverse(x, y),
+            from => from.to_wgs84(x, y),
+        };
+        match self.to {
+            Crs::Lambert93 => self.lambert93.forward(lon, lat),
+            to => to.from_wgs84(lon, lat),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sonic_rs::from_str;
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64), tolerance: f64) {
+        assert!(
+            (actual.0 - expected.0).abs() < tolerance && (actual.1 - expected.1).abs() < tolerance,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_lambert93_matches_reference_points() {
+        let lambert93 = Reprojection::new(Crs::Wgs84, Crs::Lambert93);
+
+        assert_close(lambert93.convert(3.0, 46.5), (700_000.0, 6_600_000.0), 1e-6);
+        // The scale is exactly 1 along the standard parallels
+        let (phi, e2) = (
+            49f64.to_radians(),
+            2.0 * GRS80_FLATTENING - GRS80_FLATTENING.powi(2),
+        );
+        let parallel_radius = SEMI_MAJOR_AXIS * phi.cos() / (1.0 - e2 * phi.sin().powi(2)).sqrt();
+        let west = lambert93.convert(2.9995, 49.0);
+        let east = lambert93.convert(3.0005, 49.0);
+        let length = (east.0 - west.0).hypot(east.1 - west.1);
+        assert!((length - parallel_radius * 0.001f64.to_radians()).abs() < 1e-3);
+
+        let paris = lambert93.convert(2.35, 48.85);
+        let back = Reprojection::new(Crs::Lambert93, Crs::Wgs84).convert(paris.0, paris.1);
+        assert_close(back, (2.35, 48.85), 1e-9);
+    }
+
+    #[test]
+    fn test_web_mercator_round_trip() {
+        let mercator = Reprojection::new(Crs::Wgs84, Crs::WebMercator);
+        assert_close(
+            mercator.convert(180.0, 0.0),
+            (20_037_508.342_789_244, 0.0),
+            1e-6,
+        );
+
+        let (x, y) = mercator.convert(2.35, 48.85);
+        let back = Reprojection::new(Crs::WebMercator, Crs::Lambert93).convert(x, y);
+        let expected = Reprojection::new(Crs::Wgs84, Crs::Lambert93).convert(2.35, 48.85);
+        assert_close(back, expected, 1e-3);
+    }
+
+    #[test]
+    fn test_crs_member() {
+        let crs: Value =
+            from_str(r#"{"type":"name","properties":{"name":"urn:ogc:def:crs:EPSG::2154"}}"#)
+                .unwrap();
+        assert_eq!(Crs::from_crs_member(&crs), Some(Crs::Lambert93));
+        assert_eq!(
+            Crs::from_crs_member(&Crs::WebMercator.crs_member()),
+            Some(Crs::WebMercator)
+        );
+        let crs84: Value =
+            from_str(r#"{"type":"name","properties":{"name":"urn:ogc:def:crs:OGC:1.3:CRS84"}}"#)
+                .unwrap();
+        assert_eq!(Crs::from_crs_member(&crs84), Some(Crs::Wgs84));
+    }
+}
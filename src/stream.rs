@@ -0,0 +1,265 @@
+use crate::file_operations::MyError;
+use log::{debug, info};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use sonic_rs::{to_value, to_vec, JsonValueTrait, Value as SonicValue};
+use std::fmt;
+use std::io::{Read, Write};
+
+/// Read a GeoJSON document from `reader` and write it to `writer` one member at a time.
+/// The elements of `features` are parsed, passed to `process` and written one by one,
+/// so memory use does not depend on the number of features.
+/// The other members are passed to `member` with their key, which may change them,
+/// drop them by returning `false` or stop the stream with an error.
+/// The kept members are written in their original order.
+pub fn stream_feature_collection<R, W, F, M>(
+    reader: R,
+    mut writer: W,
+    mut process: F,
+    mut member: M,
+) -> Result<usize, MyError>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&mut SonicValue),
+    M: FnMut(&str, &mut SonicValue) -> Result<bool, MyError>,
+{
+    info!("Streaming GeoJSON features");
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let mut member_error = None;
+    let count = DocumentStream {
+        writer: &mut writer,
+        process: &mut process,
+        member: &mut member,
+        member_error: &mut member_error,
+    }
+    .deserialize(&mut deserializer);
+    // The error of `member` goes through serde, which only keeps its message
+    if let Some(error) = member_error {
+        return Err(error);
+    }
+    let count = count?;
+    deserializer.end()?;
+    writer.flush()?;
+    info!("Streamed {} features", count);
+    Ok(count)
+}
+
+struct DocumentStream<'a, W, F, M> {
+    writer: &'a mut W,
+    process: &'a mut F,
+    member: &'a mut M,
+    member_error: &'a mut Option<MyError>,
+}
+
+struct FeaturesStream<'a, W, F> {
+    writer: &'a mut W,
+    process: &'a mut F,
+}
+
+fn write_raw<W: Write, E: de::Error>(writer: &mut W, bytes: &[u8]) -> Result<(), E> {
+    writer.write_all(bytes).map_err(E::custom)
+}
+
+fn write_json<W: Write, T: serde::Serialize + ?Sized, E: de::Error>(
+    writer: &mut W,
+    value: &T,
+) -> Result<(), E> {
+    let buffer = to_vec(value).map_err(E::custom)?;
+    write_raw(writer, &buffer)
+}
+
+impl<'de, W, F, M> DeserializeSeed<'de> for DocumentStream<'_, W, F, M>
+where
+    W: Write,
+    F: FnMut(&mut SonicValue),
+    M: FnMut(&str, &mut SonicValue) -> Result<bool, MyError>,
+{
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, W, F, M> Visitor<'de> for DocumentStream<'_, W, F, M>
+where
+    W: Write,
+    F: FnMut(&mut SonicValue),
+    M: FnMut(&str, &mut SonicValue) -> Result<bool, MyError>,
+{
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a GeoJSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<usize, A::Error> {
+        let mut count = 0;
+        write_raw(self.writer, b"{")?;
+        let mut first = true;
+        while let Some(key) = map.next_key::<String>()? {
+            let value = if key == "features" {
+                None
+            } else {
+                debug!("Copying member: {}", key);
+                let value: serde_json::Value = map.next_value()?;
+                let mut value: SonicValue = to_value(&value).map_err(de::Error::custom)?;
+                match (self.member)(&key, &mut value) {
+                    Ok(true) => Some(value),
+                    Ok(false) => {
+                        debug!("Member removed: {}", key);
+                        continue;
+                    }
+                    Err(error) => {
+                        let message = error.to_string();
+                        *self.member_error = Some(error);
+                        return Err(de::Error::custom(message));
+                    }
+                }
+            };
+            if !first {
+                write_raw(self.writer, b",")?;
+            }
+            first = false;
+            write_json(self.writer, &key)?;
+            write_raw(self.writer, b":")?;
+
+            match value {
+                Some(value) => write_json(self.writer, &value)?,
+                None => {
+                    count += map.next_value_seed(FeaturesStream {
+                        writer: &mut *self.writer,
+                        process: &mut *self.process,
+                    })?
+                }
+            }
+        }
+        write_raw(self.writer, b"}")?;
+        Ok(count)
+    }
+}
+
+impl<'de, W: Write, F: FnMut(&mut SonicValue)> DeserializeSeed<'de> for FeaturesStream<'_, W, F> {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, W: Write, F: FnMut(&mut SonicValue)> Visitor<'de> for FeaturesStream<'_, W, F> {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of GeoJSON features")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<usize, A::Error> {
+        let mut count = 0;
+        write_raw(self.writer, b"[")?;
+        // sonic-rs values can only be deserialized by sonic-rs itself,
+        // so each feature goes through a serde_json value first.
+        while let Some(feature) = seq.next_element::<serde_json::Value>()? {
+            let mut feature: SonicValue = to_value(&feature).map_err(de::Error::custom)?;
+            (self.process)(&mut feature);
+            if feature.is_null() {
+                debug!("Feature removed");
+                continue;
+            }
+            if count > 0 {
+                write_raw(self.writer, b",")?;
+            }
+            write_json(self.writer, &feature)?;
+            count += 1;
+        }
+        write_raw(self.writer, b"]")?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sonic_rs::{from_slice, json, JsonValueMutTrait, JsonValueTrait};
+
+    #[test]
+    fn test_stream_feature_collection_processes_each_feature() {
+        let input = r#"{"type":"FeatureCollection","name":"test","features":[
+            {"type":"Feature","properties":{"id":1},"geometry":null},
+            {"type":"Feature","properties":{"id":2},"geometry":null}
+        ],"bbox":[0,0,1,1]}"#;
+        let mut output = Vec::new();
+
+        let count = stream_feature_collection(
+            input.as_bytes(),
+            &mut output,
+            |feature| {
+                if let Some(properties) = feature
+                    .get_mut("properties")
+                    .and_then(|p| p.as_object_mut())
+                {
+                    properties.insert("streamed", true);
+                }
+            },
+            |_, _| Ok(true),
+        )
+        .unwrap();
+
+        assert_eq!(count, 2);
+        let written: SonicValue = from_slice(&output).unwrap();
+        assert_eq!(
+            written,
+            json!({"type":"FeatureCollection","name":"test","features":[
+                {"type":"Feature","properties":{"id":1,"streamed":true},"geometry":null},
+                {"type":"Feature","properties":{"id":2,"streamed":true},"geometry":null}
+            ],"bbox":[0,0,1,1]})
+        );
+        assert!(written["features"].is_array());
+    }
+
+    #[test]
+    fn test_stream_feature_collection_rejects_invalid_json() {
+        let input = r#"{"type":"FeatureCollection","features":[{"type":"Feature"},"#;
+
+        let result =
+            stream_feature_collection(input.as_bytes(), Vec::new(), |_| {}, |_, _| Ok(true));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_stream_feature_collection_removes_members() {
+        let input = r#"{"name":"test","type":"FeatureCollection","features":[],"bbox":[0,0,1,1]}"#;
+        let mut output = Vec::new();
+
+        stream_feature_collection(
+            input.as_bytes(),
+            &mut output,
+            |_| {},
+            |key, _| Ok(key == "type"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"{"type":"FeatureCollection","features":[]}"#
+        );
+    }
+
+    #[test]
+    fn test_stream_feature_collection_stops_on_member_error() {
+        let input = r#"{"type":"Feature","properties":{},"geometry":null}"#;
+
+        let result = stream_feature_collection(
+            input.as_bytes(),
+            Vec::new(),
+            |_| {},
+            |key, _| match key {
+                "type" => Err(MyError::StreamingNotSupported("a Feature".to_string())),
+                _ => Ok(true),
+            },
+        );
+
+        assert!(matches!(result, Err(MyError::StreamingNotSupported(_))));
+    }
+}
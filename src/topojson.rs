@@ -0,0 +1,601 @@
+use crate::geo_operations::{geometry_bbox, EMPTY_BBOX};
+use crate::topology::{build_topology, geometry_lines, stitch_arcs};
+use log::{debug, info};
+use sonic_rs::{Array, JsonContainerTrait, JsonValueTrait, Object, Value};
+
+/// Integer grid the coordinates are snapped to (the TopoJSON `transform`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Transform {
+    scale: (f64, f64),
+    translate: (f64, f64),
+}
+
+impl Transform {
+    fn new(bbox: [f64; 4], quantization: u32) -> Option<Self> {
+        if quantization < 2 || bbox[0] > bbox[2] {
+            return None;
+        }
+        let steps = (quantization - 1) as f64;
+        let scale = |min: f64, max: f64| if max > min { (max - min) / steps } else { 1.0 };
+        Some(Transform {
+            scale: (scale(bbox[0], bbox[2]), scale(bbox[1], bbox[3])),
+            translate: (bbox[0], bbox[1]),
+        })
+    }
+
+    fn from_value(transform: &Value) -> Option<Self> {
+        Some(Transform {
+            scale: xy(transform.get("scale")?)?,
+            translate: xy(transform.get("translate")?)?,
+        })
+    }
+
+    fn dequantize(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            x * self.scale.0 + self.translate.0,
+            y * self.scale.1 + self.translate.1,
+        )
+    }
+
+    fn quantize(&self, x: f64, y: f64) -> (i64, i64) {
+        (
+            ((x - self.translate.0) / self.scale.0).round() as i64,
+            ((y - self.translate.1) / self.scale.1).round() as i64,
+        )
+    }
+}
+
+fn float(number: f64) -> Value {
+    Value::new_f64(number).unwrap_or_default()
+}
+
+fn xy(position: &Value) -> Option<(f64, f64)> {
+    Some((position.get(0)?.as_f64()?, position.get(1)?.as_f64()?))
+}
+
+/// Quantize and delta-encode an arc. Positions that end up on the same grid
+/// cell as the previous one are dropped, as long as the arc keeps enough
+/// positions for the rings it belongs to to stay valid: four for a closed arc,
+/// which is a ring by itself, and three otherwise, so that a ring made of two
+/// arcs never ends up with less than four positions.
+fn encode_arc(arc: &Value, transform: Option<&Transform>) -> Value {
+    let Some(transform) = transform else {
+        return arc.clone();
+    };
+    let points: Vec<(i64, i64)> = arc
+        .as_array()
+        .map(|positions| {
+            positions
+                .iter()
+                .filter_map(xy)
+                .map(|(x, y)| transform.quantize(x, y))
+                .collect()
+        })
+        .unwrap_or_default();
+    let is_closed = points.len() > 1 && points.first() == points.last();
+    let min_len = points.len().min(if is_closed { 4 } else { 3 });
+
+    let mut encoded = Array::new();
+    let mut previous: Option<(i64, i64)> = None;
+    for (i, point) in points.iter().enumerate() {
+        let remaining = points.len() - i - 1;
+        let delta = match previous {
+            None => *point,
+            Some(previous) if previous == *point && encoded.len() + remaining >= min_len => {
+                continue
+            }
+            Some(previous) => (point.0 - previous.0, point.1 - previous.1),
+        };
+        encoded.push(Value::from(vec![delta.0, delta.1]));
+        previous = Some(*point);
+    }
+    encoded.into_value()
+}
+
+fn encode_position(position: &Value, transform: Option<&Transform>) -> Value {
+    match (transform, xy(position)) {
+        (Some(transform), Some((x, y))) => {
+            let (x, y) = transform.quantize(x, y);
+            Value::from(vec![x, y])
+        }
+        _ => position.clone(),
+    }
+}
+
+/// Encode a GeoJSON geometry as a TopoJSON geometry object,
+/// taking the arc references of its lines and rings from `refs` in order.
+fn encode_geometry(
+    geometry: &Value,
+    refs: &mut impl Iterator<Item = Vec<i64>>,
+    transform: Option<&Transform>,
+) -> Object {
+    let mut object = Object::new();
+    let geometry_type = geometry.get("type").and_then(|t| t.as_str());
+    object.insert("type", geometry_type.map_or(Value::default(), Value::from));
+
+    let coordinates = geometry.get("coordinates");
+    let parts = |value: Option<&Value>| value.and_then(|v| v.as_array()).map_or(0, |a| a.len());
+    let line_refs =
+        |refs: &mut dyn Iterator<Item = Vec<i64>>| Value::from(refs.next().unwrap_or_default());
+
+    match geometry_type {
+        Some("Point") => {
+            if let Some(position) = coordinates {
+                object.insert("coordinates", encode_position(position, transform));
+            }
+        }
+        Some("MultiPoint") => {
+            let positions: Array = coordinates
+                .and_then(|c| c.as_array())
+                .map(|c| c.iter().map(|p| encode_position(p, transform)).collect())
+                .unwrap_or_default();
+            object.insert("coordinates", positions.into_value());
+        }
+        Some("LineString") => {
+            object.insert("arcs", line_refs(refs));
+        }
+        Some("MultiLineString") | Some("Polygon") => {
+            let lines: Array = (0..parts(coordinates)).map(|_| line_refs(refs)).collect();
+            object.insert("arcs", lines.into_value());
+        }
+        Some("MultiPolygon") => {
+            let polygons: Array = coordinates
+                .and_then(|c| c.as_array())
+                .map(|polygons| {
+                    polygons
+                        .iter()
+                        .map(|polygon| {
+                            let rings: Array =
+                                (0..parts(Some(polygon))).map(|_| line_refs(refs)).collect();
+                            rings.into_value()
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            object.insert("arcs", polygons.into_value());
+        }
+        Some("GeometryCollection") => {
+            let geometries: Array = geometry
+                .get("geometries")
+                .and_then(|g| g.as_array())
+                .map(|geometries| {
+                    geometries
+                        .iter()
+                        .map(|child| encode_geometry(child, refs, transform).into_value())
+                        .collect()
+                })
+                .unwrap_or_default();
+            object.insert("geometries", geometries.into_value());
+        }
+        _ => debug!("Unsupported geometry type: {:?}", geometry_type),
+    }
+    object
+}
+
+/// Convert a GeoJSON document to a TopoJSON `Topology`.
+/// Lines and rings are cut into shared arcs stored only once. When `quantization`
+/// is at least 2, coordinates are snapped to a grid of that many steps and
+/// arcs are delta-encoded; 0 keeps the original coordinates.
+/// The whole document becomes the object named `object_name`.
+pub fn geojson_to_topojson(geojson: &Value, object_name: &str, quantization: u32) -> Value {
+    info!(
+        "Converting GeoJSON to TopoJSON, quantization: {}",
+        quantization
+    );
+    let null = Value::default();
+    let is_collection = geojson.get("type").and_then(|t| t.as_str()) == Some("FeatureCollection");
+    let features: Vec<&Value> = if is_collection {
+        geojson
+            .get("features")
+            .and_then(|f| f.as_array())
+            .map(|f| f.iter().collect())
+            .unwrap_or_default()
+    } else {
+        vec![geojson]
+    };
+    let geometries: Vec<&Value> = features
+        .iter()
+        .map(
+            |feature| match feature.get("type").and_then(|t| t.as_str()) {
+                Some("Feature") => feature.get("geometry").unwrap_or(&null),
+                _ => feature,
+            },
+        )
+        .collect();
+
+    let lines: Vec<(&Value, bool)> = geometries.iter().flat_map(|g| geometry_lines(g)).collect();
+    let topology = build_topology(&lines);
+
+    let mut bbox = EMPTY_BBOX;
+    for geometry in &geometries {
+        geometry_bbox(geometry, &mut bbox);
+    }
+    let transform = Transform::new(bbox, quantization);
+
+    let mut refs = topology.lines.into_iter();
+    let mut objects: Vec<Value> = Vec::new();
+    for (feature, geometry) in features.iter().zip(geometries.iter()) {
+        let mut object = encode_geometry(geometry, &mut refs, transform.as_ref());
+        if feature.get("type").and_then(|t| t.as_str()) == Some("Feature") {
+            if let Some(id) = feature.get("id") {
+                object.insert("id", id.clone());
+            }
+            if let Some(properties) = feature.get("properties").filter(|p| !p.is_null()) {
+                object.insert("properties", properties.clone());
+            }
+        }
+        objects.push(object.into_value());
+    }
+
+    let object = if is_collection {
+        let mut collection = Object::new();
+        collection.insert("type", "GeometryCollection");
+        collection.insert("geometries", objects);
+        collection.into_value()
+    } else {
+        objects.pop().unwrap_or_default()
+    };
+    let mut named_objects = Object::new();
+    named_objects.insert(object_name, object);
+
+    let arcs: Array = topology
+        .arcs
+        .iter()
+        .map(|arc| encode_arc(arc, transform.as_ref()))
+        .collect();
+
+    let mut topojson = Object::new();
+    topojson.insert("type", "Topology");
+    if bbox[0] <= bbox[2] {
+        topojson.insert("bbox", Value::from(bbox.map(float).to_vec()));
+    }
+    if let Some(transform) = transform {
+        let mut transform_object = Object::new();
+        transform_object.insert(
+            "scale",
+            Value::from(vec![float(transform.scale.0), float(transform.scale.1)]),
+        );
+        transform_object.insert(
+            "translate",
+            Value::from(vec![
+                float(transform.translate.0),
+                float(transform.translate.1),
+            ]),
+        );
+        topojson.insert("transform", transform_object);
+    }
+    topojson.insert("objects", named_objects);
+    topojson.insert("arcs", arcs);
+    info!("TopoJSON conversion completed");
+    topojson.into_value()
+}
+
+pub fn is_topojson(json: &Value) -> bool {
+    json.get("type").and_then(|t| t.as_str()) == Some("Topology")
+}
+
+/// Undo the quantization and delta encoding of every arc.
+fn decode_arcs(arcs: &Value, transform: Option<&Transform>) -> Vec<Value> {
+    let Some(arcs) = arcs.as_array() else {
+        return Vec::new();
+    };
+    arcs.iter()
+        .map(|arc| {
+            let Some(transform) = transform else {
+                return arc.clone();
+            };
+            let (mut x, mut y) = (0.0, 0.0);
+            let positions: Array = arc
+                .as_array()
+                .map(|positions| {
+                    positions
+                        .iter()
+                        .filter_map(xy)
+                        .map(|(dx, dy)| {
+                            x += dx;
+                            y += dy;
+                            let (x, y) = transform.dequantize(x, y);
+                            Value::from(vec![float(x), float(y)])
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            positions.into_value()
+        })
+        .collect()
+}
+
+fn decode_position(position: &Value, transform: Option<&Transform>) -> Value {
+    match (transform, xy(position)) {
+        (Some(transform), Some((x, y))) => {
+            let (x, y) = transform.dequantize(x, y);
+            Value::from(vec![float(x), float(y)])
+        }
+        _ => position.clone(),
+    }
+}
+
+fn decode_line(refs: &Value, arcs: &[Value]) -> Value {
+    let refs: Vec<i64> = refs
+        .as_array()
+        .map(|refs| refs.iter().filter_map(|r| r.as_i64()).collect())
+        .unwrap_or_default();
+    stitch_arcs(arcs, &refs).into_value()
+}
+
+/// Map every element of an array, or return an empty array.
+fn map_array(value: Option<&Value>, f: impl Fn(&Value) -> Value) -> Value {
+    let mapped: Array = value
+        .and_then(|v| v.as_array())
+        .map(|array| array.iter().map(f).collect())
+        .unwrap_or_default();
+    mapped.into_value()
+}
+
+/// Decode a TopoJSON geometry object into a GeoJSON geometry (null for null geometries).
+fn decode_geometry(object: &Value, arcs: &[Value], transform: Option<&Transform>) -> Value {
+    let geometry_type = object.get("type").and_then(|t| t.as_str());
+    let coordinates = object.get("coordinates");
+    let object_arcs = object.get("arcs");
+    let decoded = match geometry_type {
+        Some("Point") => coordinates.map(|c| decode_position(c, transform)),
+        Some("MultiPoint") => Some(map_array(coordinates, |p| decode_position(p, transform))),
+        Some("LineString") => object_arcs.map(|refs| decode_line(refs, arcs)),
+        Some("MultiLineString") | Some("Polygon") => {
+            Some(map_array(object_arcs, |refs| decode_line(refs, arcs)))
+        }
+        Some("MultiPolygon") => Some(map_array(object_arcs, |polygon| {
+            map_array(Some(polygon), |refs| decode_line(refs, arcs))
+        })),
+        Some("GeometryCollection") => {
+            let mut collection = Object::new();
+            collection.insert("type", "GeometryCollection");
+            collection.insert(
+                "geometries",
+                map_array(object.get("geometries"), |child| {
+                    decode_geometry(child, arcs, transform)
+                }),
+            );
+            return collection.into_value();
+        }
+        _ => None,
+    };
+
+    match (geometry_type, decoded) {
+        (Some(geometry_type), Some(coordinates)) => {
+            let mut geometry = Object::new();
+            geometry.insert("type", geometry_type);
+            geometry.insert("coordinates", coordinates);
+            geometry.into_value()
+        }
+        _ => {
+            debug!("Null or unsupported geometry type: {:?}", geometry_type);
+            Value::default()
+        }
+    }
+}
+
+fn decode_feature(object: &Value, arcs: &[Value], transform: Option<&Transform>) -> Value {
+    let mut feature = Object::new();
+    feature.insert("type", "Feature");
+    if let Some(id) = object.get("id") {
+        feature.insert("id", id.clone());
+    }
+    feature.insert(
+        "properties",
+        object
+            .get("properties")
+            .cloned()
+            .unwrap_or_else(|| Object::new().into_value()),
+    );
+    feature.insert("geometry", decode_geometry(object, arcs, transform));
+    feature.into_value()
+}
+
+/// Convert a TopoJSON `Topology` to a GeoJSON FeatureCollection.
+/// The geometries of every object become features, in the order of the objects.
+pub fn topojson_to_geojson(topojson: &Value) -> Value {
+    info!("Converting TopoJSON to GeoJSON");
+    let transform = topojson.get("transform").and_then(Transform::from_value);
+    let arcs = topojson
+        .get("arcs")
+        .map(|arcs| decode_arcs(arcs, transform.as_ref()))
+        .unwrap_or_default();
+
+    let mut features = Array::new();
+    if let Some(objects) = topojson.get("objects").and_then(|o| o.as_object()) {
+        for (name, object) in objects.iter() {
+            debug!("Decoding TopoJSON object: {}", name);
+            if object.get("type").and_then(|t| t.as_str()) == Some("GeometryCollection") {
+                if let Some(geometries) = object.get("geometries").and_then(|g| g.as_array()) {
+                    for geometry in geometries.iter() {
+                        features.push(decode_feature(geometry, &arcs, transform.as_ref()));
+                    }
+                }
+            } else {
+                features.push(decode_feature(object, &arcs, transform.as_ref()));
+            }
+        }
+    }
+
+    let mut geojson = Object::new();
+    geojson.insert("type", "FeatureCollection");
+    if let Some(bbox) = topojson.get("bbox") {
+        geojson.insert("bbox", bbox.clone());
+    }
+    geojson.insert("features", features);
+    info!("TopoJSON conversion completed");
+    geojson.into_value()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sonic_rs::json;
+
+    fn two_squares() -> Value {
+        json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "id": "left",
+                    "properties": {"name": "left"},
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 1], [0, 0]]]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": null,
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[1, 0], [2, 0], [2, 1], [1, 1], [1, 0]]]
+                    }
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn test_geojson_to_topojson_with_quantization() {
+        let topojson = geojson_to_topojson(&two_squares(), "squares", 3);
+
+        assert_eq!(
+            topojson,
+            json!({
+                "type": "Topology",
+                "bbox": [0.0, 0.0, 2.0, 1.0],
+                "transform": {"scale": [1.0, 0.5], "translate": [0.0, 0.0]},
+                "objects": {
+                    "squares": {
+                        "type": "GeometryCollection",
+                        "geometries": [
+                            {"type": "Polygon", "arcs": [[0, 1]], "id": "left", "properties": {"name": "left"}},
+                            {"type": "Polygon", "arcs": [[2, -1]]}
+                        ]
+                    }
+                },
+                "arcs": [
+                    [[1, 0], [0, 2]],
+                    [[1, 2], [-1, 0], [0, -2], [1, 0]],
+                    [[1, 0], [1, 0], [0, 2], [-1, 0]]
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_geojson_to_topojson_without_quantization() {
+        let point = json!({"type": "Point", "coordinates": [1.5, 2.5]});
+
+        let topojson = geojson_to_topojson(&point, "point", 0);
+
+        assert_eq!(
+            topojson,
+            json!({
+                "type": "Topology",
+                "bbox": [1.5, 2.5, 1.5, 2.5],
+                "objects": {"point": {"type": "Point", "coordinates": [1.5, 2.5]}},
+                "arcs": []
+            })
+        );
+    }
+
+    #[test]
+    fn test_topojson_to_geojson_round_trip() {
+        let topojson = geojson_to_topojson(&two_squares(), "squares", 3);
+
+        let geojson = topojson_to_geojson(&topojson);
+
+        assert_eq!(
+            geojson,
+            json!({
+                "type": "FeatureCollection",
+                "bbox": [0.0, 0.0, 2.0, 1.0],
+                "features": [
+                    {
+                        "type": "Feature",
+                        "id": "left",
+                        "properties": {"name": "left"},
+                        "geometry": {
+                            "type": "Polygon",
+                            "coordinates": [[[1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0], [1.0, 0.0]]]
+                        }
+                    },
+                    {
+                        "type": "Feature",
+                        "properties": {},
+                        "geometry": {
+                            "type": "Polygon",
+                            "coordinates": [[[1.0, 0.0], [2.0, 0.0], [2.0, 1.0], [1.0, 1.0], [1.0, 0.0]]]
+                        }
+                    }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_quantization_keeps_collapsed_rings_valid() {
+        let geojson = json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "properties": {},
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[0, 0], [100, 0], [100, 100], [0, 100], [0, 0]]]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": {},
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[50, 50], [50.1, 50], [50.1, 50.1], [50, 50.1], [50, 50]]]
+                    }
+                }
+            ]
+        });
+
+        let topojson = geojson_to_topojson(&geojson, "rings", 10);
+        let geojson = topojson_to_geojson(&topojson);
+
+        let features = geojson["features"].as_array().unwrap();
+        for feature in features.iter() {
+            let ring = feature["geometry"]["coordinates"][0].as_array().unwrap();
+            assert!(ring.len() >= 4, "ring with {} positions", ring.len());
+            assert_eq!(ring.first(), ring.last());
+        }
+    }
+
+    #[test]
+    fn test_topojson_to_geojson_with_points_and_null_geometry() {
+        let topojson = json!({
+            "type": "Topology",
+            "transform": {"scale": [0.5, 0.5], "translate": [10.0, 20.0]},
+            "objects": {
+                "point": {"type": "Point", "coordinates": [2, 4], "properties": {"a": 1}},
+                "nothing": {"type": null}
+            },
+            "arcs": []
+        });
+
+        let geojson = topojson_to_geojson(&topojson);
+
+        assert_eq!(
+            geojson["features"],
+            json!([
+                {
+                    "type": "Feature",
+                    "properties": {"a": 1},
+                    "geometry": {"type": "Point", "coordinates": [11.0, 22.0]}
+                },
+                {"type": "Feature", "properties": {}, "geometry": null}
+            ])
+        );
+    }
+}
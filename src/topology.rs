@@ -0,0 +1,370 @@
+use crate::geo_operations::{simplify_part, Simplification};
+use log::{debug, info};
+use sonic_rs::{Array, JsonContainerTrait, JsonValueMutTrait, JsonValueTrait, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Position key made of the bits of x and y, so positions can be hashed and compared exactly.
+type PointKey = (u64, u64);
+
+/// Lines and rings of a set of geometries, cut into arcs at the junctions
+/// where neighbouring geometries start or stop sharing a border.
+/// Every shared arc is stored only once.
+#[derive(Debug, Default)]
+pub struct Topology {
+    /// Arrays of positions, each one shared by every line that uses it.
+    pub arcs: Vec<Value>,
+    /// Arc references of every line and ring, in the order `geometry_lines` visits them.
+    /// A negative reference `!i` means arc `i` is used reversed (TopoJSON convention).
+    pub lines: Vec<Vec<i64>>,
+}
+
+fn point_key(position: &Value) -> Option<PointKey> {
+    let x = position.get(0)?.as_f64()?;
+    let y = position.get(1)?.as_f64()?;
+    // -0.0 and 0.0 are the same point
+    Some(((x + 0.0).to_bits(), (y + 0.0).to_bits()))
+}
+
+fn line_keys(line: &Value) -> Option<Vec<PointKey>> {
+    line.as_array()?.iter().map(point_key).collect()
+}
+
+/// Collect the LineStrings and polygon rings of a geometry, in document order.
+/// The boolean tells whether the line is a polygon ring.
+pub fn geometry_lines(geometry: &Value) -> Vec<(&Value, bool)> {
+    let mut lines = Vec::new();
+    let geometry_type = geometry.get("type").and_then(|t| t.as_str());
+    if geometry_type == Some("GeometryCollection") {
+        if let Some(geometries) = geometry.get("geometries").and_then(|g| g.as_array()) {
+            for child in geometries.iter() {
+                lines.extend(geometry_lines(child));
+            }
+        }
+        return lines;
+    }
+    let Some(coordinates) = geometry.get("coordinates") else {
+        return lines;
+    };
+    fn parts(value: &Value) -> Vec<&Value> {
+        match value.as_array() {
+            Some(array) => array.iter().collect(),
+            None => Vec::new(),
+        }
+    }
+    match geometry_type {
+        Some("LineString") => lines.push((coordinates, false)),
+        Some("MultiLineString") => lines.extend(parts(coordinates).into_iter().map(|l| (l, false))),
+        Some("Polygon") => lines.extend(parts(coordinates).into_iter().map(|r| (r, true))),
+        Some("MultiPolygon") => lines.extend(
+            parts(coordinates)
+                .into_iter()
+                .flat_map(parts)
+                .map(|r| (r, true)),
+        ),
+        _ => {}
+    }
+    lines
+}
+
+/// Mutable version of `geometry_lines`, visiting the lines in the same order.
+pub fn geometry_lines_mut(geometry: &mut Value) -> Vec<(&mut Value, bool)> {
+    let geometry_type = geometry
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .to_string();
+    if geometry_type == "GeometryCollection" {
+        return match geometry
+            .get_mut("geometries")
+            .and_then(|g| g.as_array_mut())
+        {
+            Some(geometries) => geometries.iter_mut().flat_map(geometry_lines_mut).collect(),
+            None => Vec::new(),
+        };
+    }
+    let Some(coordinates) = geometry.get_mut("coordinates") else {
+        return Vec::new();
+    };
+    fn parts(value: &mut Value) -> Vec<&mut Value> {
+        match value.as_array_mut() {
+            Some(array) => array.iter_mut().collect(),
+            None => Vec::new(),
+        }
+    }
+    match geometry_type.as_str() {
+        "LineString" => vec![(coordinates, false)],
+        "MultiLineString" => parts(coordinates).into_iter().map(|l| (l, false)).collect(),
+        "Polygon" => parts(coordinates).into_iter().map(|r| (r, true)).collect(),
+        "MultiPolygon" => parts(coordinates)
+            .into_iter()
+            .flat_map(parts)
+            .map(|r| (r, true))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Find the junctions: the line endpoints, and the points that do not have
+/// the same neighbours in every line or ring that goes through them.
+fn find_junctions(lines: &[(Vec<PointKey>, bool)]) -> HashSet<PointKey> {
+    let mut neighbours: HashMap<PointKey, (PointKey, PointKey)> = HashMap::new();
+    let mut junctions: HashSet<PointKey> = HashSet::new();
+
+    for (keys, is_ring) in lines {
+        let points = if *is_ring && keys.len() > 1 {
+            &keys[..keys.len() - 1]
+        } else {
+            &keys[..]
+        };
+        let len = points.len();
+        for (i, key) in points.iter().enumerate() {
+            let (previous, next) = if *is_ring {
+                (points[(i + len - 1) % len], points[(i + 1) % len])
+            } else if i == 0 || i == len - 1 {
+                junctions.insert(*key);
+                continue;
+            } else {
+                (points[i - 1], points[i + 1])
+            };
+            let pair = if previous <= next {
+                (previous, next)
+            } else {
+                (next, previous)
+            };
+            match neighbours.get(key) {
+                Some(seen) if *seen != pair => {
+                    junctions.insert(*key);
+                }
+                Some(_) => {}
+                None => {
+                    neighbours.insert(*key, pair);
+                }
+            }
+        }
+    }
+    junctions
+}
+
+/// Cut every line and ring at the junctions and deduplicate the resulting arcs.
+/// Lines and rings with invalid positions get no arcs.
+pub fn build_topology(lines: &[(&Value, bool)]) -> Topology {
+    info!("Building topology from {} lines and rings", lines.len());
+    let keyed: Vec<(Vec<PointKey>, bool)> = lines
+        .iter()
+        .map(|(line, is_ring)| (line_keys(line).unwrap_or_default(), *is_ring))
+        .collect();
+    let junctions = find_junctions(&keyed);
+
+    let mut topology = Topology::default();
+    let mut arc_indexes: HashMap<Vec<PointKey>, i64> = HashMap::new();
+
+    for ((line, is_ring), (keys, _)) in lines.iter().zip(keyed.iter()) {
+        let positions: Vec<&Value> = line
+            .as_array()
+            .map(|a| a.iter().collect())
+            .unwrap_or_default();
+        if keys.len() < 2 || keys.len() != positions.len() {
+            topology.lines.push(Vec::new());
+            continue;
+        }
+
+        // Rings are rotated to start on a junction, or on their smallest point
+        // when they have none so that identical rings give identical arcs.
+        let order: Vec<usize> = if *is_ring {
+            let len = keys.len() - 1;
+            let start = (0..len)
+                .find(|i| junctions.contains(&keys[*i]))
+                .unwrap_or_else(|| (0..len).min_by_key(|i| keys[*i]).unwrap_or(0));
+            (0..=len).map(|i| (start + i) % len).collect()
+        } else {
+            (0..keys.len()).collect()
+        };
+
+        let mut refs = Vec::new();
+        let mut arc_start = 0;
+        for position in 1..order.len() {
+            let is_last = position == order.len() - 1;
+            if !is_last && !junctions.contains(&keys[order[position]]) {
+                continue;
+            }
+            let arc = &order[arc_start..=position];
+            let arc_keys: Vec<PointKey> = arc.iter().map(|i| keys[*i]).collect();
+            let reversed_keys: Vec<PointKey> = arc_keys.iter().rev().copied().collect();
+
+            let reference = if let Some(index) = arc_indexes.get(&arc_keys) {
+                *index
+            } else if let Some(index) = arc_indexes.get(&reversed_keys) {
+                !*index
+            } else {
+                let index = topology.arcs.len() as i64;
+                let arc_positions: Array = arc.iter().map(|i| positions[*i].clone()).collect();
+                topology.arcs.push(arc_positions.into_value());
+                arc_indexes.insert(arc_keys, index);
+                index
+            };
+            refs.push(reference);
+            arc_start = position;
+        }
+        topology.lines.push(refs);
+    }
+
+    info!(
+        "Topology built with {} arcs for {} lines and rings",
+        topology.arcs.len(),
+        topology.lines.len()
+    );
+    topology
+}
+
+/// Rebuild the positions of a line from its arcs.
+pub(crate) fn stitch_arcs(arcs: &[Value], refs: &[i64]) -> Array {
+    let mut positions = Array::new();
+    for reference in refs {
+        let (index, reversed) = if *reference < 0 {
+            (!*reference as usize, true)
+        } else {
+            (*reference as usize, false)
+        };
+        let Some(arc) = arcs.get(index).and_then(|a| a.as_array()) else {
+            continue;
+        };
+        let mut arc_positions: Vec<&Value> = arc.iter().collect();
+        if reversed {
+            arc_positions.reverse();
+        }
+        // The first position of an arc is the last position of the previous one
+        let skip = usize::from(!positions.is_empty());
+        for position in arc_positions.into_iter().skip(skip) {
+            positions.push(position.clone());
+        }
+    }
+    positions
+}
+
+/// Simplify the lines and rings of all the geometries together, so that the
+/// borders shared by neighbouring geometries are simplified once and stay shared.
+pub fn simplify_geometries_with_topology(
+    geometries: &mut [&mut Value],
+    simplification: &Simplification,
+) {
+    info!("Starting topology-preserving simplification");
+    let topology = {
+        let lines: Vec<(&Value, bool)> = geometries
+            .iter()
+            .flat_map(|geometry| geometry_lines(geometry))
+            .collect();
+        build_topology(&lines)
+    };
+    let is_ring: Vec<bool> = geometries
+        .iter()
+        .flat_map(|geometry| geometry_lines(geometry).into_iter().map(|(_, r)| r))
+        .collect();
+
+    // Arcs closing a ring on their own need 4 positions, the others 2.
+    // When a ring made of several arcs collapses, its arcs keep one more
+    // position each until the ring is valid again.
+    let mut min_points: Vec<usize> = topology
+        .arcs
+        .iter()
+        .map(|arc| match arc.as_array() {
+            Some(positions) if positions.len() > 1 && positions.first() == positions.last() => 4,
+            _ => 2,
+        })
+        .collect();
+    let simplified_arcs = loop {
+        let mut arcs = topology.arcs.clone();
+        for (arc, min) in arcs.iter_mut().zip(min_points.iter()) {
+            simplify_part(arc, simplification, *min);
+        }
+
+        let mut changed = false;
+        for (refs, ring) in topology.lines.iter().zip(is_ring.iter()) {
+            if *ring && !refs.is_empty() && stitch_arcs(&arcs, refs).len() < 4 {
+                for reference in refs {
+                    let index = if *reference < 0 {
+                        !*reference
+                    } else {
+                        *reference
+                    } as usize;
+                    let len = topology.arcs[index].as_array().map_or(0, |a| a.len());
+                    if min_points[index] < len {
+                        min_points[index] += 1;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        if !changed {
+            break arcs;
+        }
+        debug!("Some rings collapsed, simplifying their arcs again");
+    };
+
+    let mut refs = topology.lines.iter();
+    for geometry in geometries.iter_mut() {
+        for (line, _) in geometry_lines_mut(geometry) {
+            match refs.next() {
+                Some(line_refs) if !line_refs.is_empty() => {
+                    *line = stitch_arcs(&simplified_arcs, line_refs).into_value();
+                }
+                _ => {}
+            }
+        }
+    }
+    info!("Topology-preserving simplification completed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo_operations::ToleranceUnit;
+    use sonic_rs::json;
+
+    fn two_squares() -> (Value, Value) {
+        let left = json!({
+            "type": "Polygon",
+            "coordinates": [[[0.0, 0.0], [1.0, 0.0], [1.0, 0.5], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0]]]
+        });
+        let right = json!({
+            "type": "Polygon",
+            "coordinates": [[[1.0, 0.0], [2.0, 0.0], [2.0, 1.0], [1.0, 1.0], [1.0, 0.5], [1.0, 0.0]]]
+        });
+        (left, right)
+    }
+
+    #[test]
+    fn test_build_topology_shares_common_border() {
+        let (left, right) = two_squares();
+        let mut lines = geometry_lines(&left);
+        lines.extend(geometry_lines(&right));
+
+        let topology = build_topology(&lines);
+
+        assert_eq!(topology.arcs.len(), 3);
+        assert_eq!(topology.lines, vec![vec![0, 1], vec![2, !0]]);
+        assert_eq!(
+            topology.arcs[1],
+            json!([[1.0, 1.0], [0.0, 1.0], [0.0, 0.0], [1.0, 0.0]])
+        );
+    }
+
+    #[test]
+    fn test_simplify_with_topology_keeps_shared_border_identical() {
+        let (mut left, mut right) = two_squares();
+        let simplification = Simplification::DouglasPeucker {
+            tolerance: 0.1,
+            unit: ToleranceUnit::Degrees,
+        };
+
+        simplify_geometries_with_topology(&mut [&mut left, &mut right], &simplification);
+
+        assert_eq!(
+            left["coordinates"],
+            json!([[[1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0], [1.0, 0.0]]])
+        );
+        assert_eq!(
+            right["coordinates"],
+            json!([[[1.0, 0.0], [2.0, 0.0], [2.0, 1.0], [1.0, 1.0], [1.0, 0.0]]])
+        );
+    }
+}
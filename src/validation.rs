@@ -0,0 +1,461 @@
+use crate::file_operations::{read_file_content, MyError, GEOMETRY_TYPES};
+use crate::geo_operations::ring_signed_area;
+use log::{debug, info};
+use sonic_rs::{from_str, JsonContainerTrait, JsonValueTrait, Value};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The document breaks a MUST of RFC 7946.
+    Error,
+    /// The document breaks a SHOULD of RFC 7946.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+/// A problem found in a document, located by its JSON pointer (RFC 6901).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub pointer: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pointer = if self.pointer.is_empty() {
+            "/"
+        } else {
+            &self.pointer
+        };
+        write!(f, "{} at {}: {}", self.severity, pointer, self.message)
+    }
+}
+
+struct Validator {
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Escape a member name for a JSON pointer.
+fn escape(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+fn child(pointer: &str, segment: impl fmt::Display) -> String {
+    format!("{}/{}", pointer, escape(&segment.to_string()))
+}
+
+impl Validator {
+    fn report(&mut self, pointer: &str, severity: Severity, message: impl Into<String>) {
+        let diagnostic = Diagnostic {
+            pointer: pointer.to_string(),
+            severity,
+            message: message.into(),
+        };
+        debug!("{}", diagnostic);
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn error(&mut self, pointer: &str, message: impl Into<String>) {
+        self.report(pointer, Severity::Error, message);
+    }
+
+    fn warning(&mut self, pointer: &str, message: impl Into<String>) {
+        self.report(pointer, Severity::Warning, message);
+    }
+
+    fn document(&mut self, value: &Value) {
+        match value.get("type").and_then(|t| t.as_str()) {
+            Some("FeatureCollection") => self.feature_collection(value, ""),
+            Some("Feature") => self.feature(value, ""),
+            _ => self.geometry(value, "", 0),
+        }
+    }
+
+    /// Check the `type` member and return it when it is a string.
+    fn object_type<'a>(&mut self, value: &'a Value, pointer: &str) -> Option<&'a str> {
+        if !value.is_object() {
+            self.error(pointer, "expected an object");
+            return None;
+        }
+        match value.get("type") {
+            None => {
+                self.error(pointer, "missing \"type\" member");
+                None
+            }
+            Some(kind) => {
+                let kind = kind.as_str();
+                if kind.is_none() {
+                    self.error(&child(pointer, "type"), "\"type\" must be a string");
+                }
+                kind
+            }
+        }
+    }
+
+    fn feature_collection(&mut self, value: &Value, pointer: &str) {
+        self.bbox(value, pointer);
+        let features_pointer = child(pointer, "features");
+        match value.get("features") {
+            None => self.error(pointer, "FeatureCollection without \"features\" member"),
+            Some(features) => match features.as_array() {
+                Some(features) => {
+                    for (index, feature) in features.iter().enumerate() {
+                        self.feature(feature, &child(&features_pointer, index));
+                    }
+                }
+                None => self.error(&features_pointer, "\"features\" must be an array"),
+            },
+        }
+    }
+
+    fn feature(&mut self, value: &Value, pointer: &str) {
+        match self.object_type(value, pointer) {
+            Some("Feature") => {}
+            Some(other) => {
+                self.error(
+                    &child(pointer, "type"),
+                    format!("expected \"Feature\", found \"{}\"", other),
+                );
+                return;
+            }
+            None => return,
+        }
+        self.bbox(value, pointer);
+        if let Some(id) = value.get("id") {
+            if !id.is_str() && !id.is_number() {
+                self.error(&child(pointer, "id"), "\"id\" must be a string or a number");
+            }
+        }
+        match value.get("properties") {
+            None => self.error(pointer, "Feature without \"properties\" member"),
+            Some(properties) if !properties.is_null() && !properties.is_object() => self.error(
+                &child(pointer, "properties"),
+                "\"properties\" must be an object or null",
+            ),
+            Some(_) => {}
+        }
+        match value.get("geometry") {
+            None => self.error(pointer, "Feature without \"geometry\" member"),
+            Some(geometry) if geometry.is_null() => {}
+            Some(geometry) => self.geometry(geometry, &child(pointer, "geometry"), 0),
+        }
+    }
+
+    fn geometry(&mut self, value: &Value, pointer: &str, depth: usize) {
+        let Some(kind) = self.object_type(value, pointer) else {
+            return;
+        };
+        if !GEOMETRY_TYPES.contains(&kind) {
+            let message = match kind {
+                "Feature" | "FeatureCollection" => {
+                    format!("expected a geometry, found \"{}\"", kind)
+                }
+                "Topology" => "a TopoJSON Topology is not GeoJSON".to_string(),
+                _ => format!("unknown type \"{}\"", kind),
+            };
+            self.error(&child(pointer, "type"), message);
+            return;
+        }
+        self.bbox(value, pointer);
+
+        if kind == "GeometryCollection" {
+            if depth > 0 {
+                self.warning(pointer, "nested GeometryCollections should be avoided");
+            }
+            let geometries_pointer = child(pointer, "geometries");
+            match value.get("geometries").map(|g| g.as_array()) {
+                None => self.error(pointer, "GeometryCollection without \"geometries\" member"),
+                Some(None) => self.error(&geometries_pointer, "\"geometries\" must be an array"),
+                Some(Some(geometries)) => {
+                    for (index, geometry) in geometries.iter().enumerate() {
+                        self.geometry(geometry, &child(&geometries_pointer, index), depth + 1);
+                    }
+                }
+            }
+            return;
+        }
+
+        let Some(coordinates) = value.get("coordinates") else {
+            self.error(pointer, format!("{} without \"coordinates\" member", kind));
+            return;
+        };
+        let pointer = child(pointer, "coordinates");
+        match kind {
+            "Point" => self.position(coordinates, &pointer),
+            "MultiPoint" => {
+                self.each(coordinates, &pointer, |v, c, p| v.position(c, p));
+            }
+            "LineString" => self.line(coordinates, &pointer),
+            "MultiLineString" => {
+                self.each(coordinates, &pointer, |v, c, p| v.line(c, p));
+            }
+            "Polygon" => self.polygon(coordinates, &pointer),
+            _ => {
+                self.each(coordinates, &pointer, |v, c, p| v.polygon(c, p));
+            }
+        }
+    }
+
+    /// Run `check` on each element of an array of coordinates.
+    fn each<F>(&mut self, coordinates: &Value, pointer: &str, mut check: F) -> usize
+    where
+        F: FnMut(&mut Self, &Value, &str),
+    {
+        let Some(array) = coordinates.as_array() else {
+            self.error(pointer, "expected an array");
+            return 0;
+        };
+        for (index, element) in array.iter().enumerate() {
+            check(self, element, &child(pointer, index));
+        }
+        array.len()
+    }
+
+    fn position(&mut self, position: &Value, pointer: &str) {
+        let Some(numbers) = position.as_array() else {
+            self.error(pointer, "a position must be an array of numbers");
+            return;
+        };
+        let Some(numbers) = numbers
+            .iter()
+            .map(|n| n.as_f64())
+            .collect::<Option<Vec<f64>>>()
+        else {
+            self.error(pointer, "a position must only contain numbers");
+            return;
+        };
+        if numbers.len() < 2 {
+            self.error(pointer, "a position needs a longitude and a latitude");
+            return;
+        }
+        if numbers.len() > 3 {
+            self.warning(
+                pointer,
+                "a position should not have more than three elements",
+            );
+        }
+        if !(-180.0..=180.0).contains(&numbers[0]) {
+            self.error(
+                &child(pointer, 0),
+                format!("longitude {} is out of [-180, 180]", numbers[0]),
+            );
+        }
+        if !(-90.0..=90.0).contains(&numbers[1]) {
+            self.error(
+                &child(pointer, 1),
+                format!("latitude {} is out of [-90, 90]", numbers[1]),
+            );
+        }
+    }
+
+    fn line(&mut self, line: &Value, pointer: &str) {
+        let count = self.each(line, pointer, |v, c, p| v.position(c, p));
+        if line.is_array() && count < 2 {
+            self.error(pointer, "a LineString needs at least two positions");
+        }
+    }
+
+    fn polygon(&mut self, rings: &Value, pointer: &str) {
+        let mut exterior = true;
+        self.each(rings, pointer, |v, ring, p| {
+            v.ring(ring, p, exterior);
+            exterior = false;
+        });
+    }
+
+    fn ring(&mut self, ring: &Value, pointer: &str, exterior: bool) {
+        let count = self.each(ring, pointer, |v, c, p| v.position(c, p));
+        let Some(positions) = ring.as_array() else {
+            return;
+        };
+        if count < 4 {
+            self.error(pointer, "a linear ring needs at least four positions");
+        }
+        if let (Some(first), Some(last)) = (positions.first(), positions.last()) {
+            if count > 1 && !same_position(first, last) {
+                self.error(
+                    pointer,
+                    "a linear ring must be closed: its first and last positions must be equal",
+                );
+            }
+        }
+        // Parsers should not reject the other orientation, hence a warning
+        let area = ring_signed_area(ring);
+        if exterior && area < 0.0 {
+            self.warning(pointer, "an exterior ring should be counter-clockwise");
+        } else if !exterior && area > 0.0 {
+            self.warning(pointer, "a hole should be clockwise");
+        }
+    }
+
+    fn bbox(&mut self, value: &Value, pointer: &str) {
+        let Some(bbox) = value.get("bbox") else {
+            return;
+        };
+        let pointer = child(pointer, "bbox");
+        let Some(numbers) = bbox
+            .as_array()
+            .and_then(|b| b.iter().map(|n| n.as_f64()).collect::<Option<Vec<f64>>>())
+        else {
+            self.error(&pointer, "\"bbox\" must be an array of numbers");
+            return;
+        };
+        if numbers.len() != 4 && numbers.len() != 6 {
+            self.error(
+                &pointer,
+                format!("\"bbox\" must have 4 or 6 numbers, found {}", numbers.len()),
+            );
+            return;
+        }
+        // The west edge may be greater than the east edge across the antimeridian,
+        // but the south edge is always below the north edge.
+        let dimensions = numbers.len() / 2;
+        if numbers[1] > numbers[1 + dimensions] {
+            self.error(
+                &pointer,
+                "the south edge of \"bbox\" is above its north edge",
+            );
+        }
+    }
+}
+
+/// Whether two positions have the same ordinates, whatever their JSON representation (0 or 0.0).
+fn same_position(a: &Value, b: &Value) -> bool {
+    match (a.as_array(), b.as_array()) {
+        (Some(a), Some(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b.iter())
+                    .all(|(a, b)| a.as_f64().is_some() && a.as_f64() == b.as_f64())
+        }
+        _ => a == b,
+    }
+}
+
+/// Check a document against RFC 7946 and return the problems found, in document order.
+pub fn validate(geojson: &Value) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        diagnostics: Vec::new(),
+    };
+    validator.document(geojson);
+    info!("Validation found {} problems", validator.diagnostics.len());
+    validator.diagnostics
+}
+
+/// Read and validate one input file, as it is: a TopoJSON input is not converted to GeoJSON.
+pub fn handle_input_validation(input: &str) -> Result<Vec<Diagnostic>, MyError> {
+    info!("Validating file: {:?}", input);
+    let geojson: Value = from_str(&read_file_content(input)?)?;
+    Ok(validate(&geojson))
+}
+
+/// Whether any of the diagnostics makes the document invalid.
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_operations::read_json_file;
+    use crate::geo_operations::{rewind_geometry, WindingOrder};
+    use sonic_rs::JsonValueMutTrait;
+
+    fn diagnostics(geojson: &str) -> Vec<String> {
+        validate(&from_str(geojson).unwrap())
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_valid_documents() {
+        let mut geojson = read_json_file("data/departements.geojson").unwrap();
+        // The departements use the legacy clockwise orientation
+        let legacy = validate(&geojson);
+        assert!(!has_errors(&legacy));
+        assert!(legacy
+            .iter()
+            .all(|diagnostic| diagnostic.message.contains("clockwise")));
+        for feature in geojson["features"].as_array_mut().unwrap().iter_mut() {
+            rewind_geometry(&mut feature["geometry"], WindingOrder::Rfc7946);
+        }
+        assert_eq!(validate(&geojson), vec![]);
+        assert!(diagnostics(
+            r#"{"type":"Feature","id":1,"properties":null,"geometry":null,"bbox":[170,-10,-170,10]}"#
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_validate_reports_pointers_and_severities() {
+        let geojson = r#"{"type":"FeatureCollection","features":[
+            {"type":"Feature","properties":{},"geometry":{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,1]]]}},
+            {"type":"Feature","geometry":{"type":"LineString","coordinates":[[200,0,0,0]]}},
+            {"type":"Feature","properties":{},"geometry":{"type":"GeometryCollection","geometries":[{"type":"GeometryCollection","geometries":[{"type":"Circle"}]}]}},
+            {"type":"Point","coordinates":[0,0]}
+        ],"bbox":[0,10,1,0]}"#;
+
+        assert_eq!(
+            diagnostics(geojson),
+            vec![
+                "error at /bbox: the south edge of \"bbox\" is above its north edge",
+                "error at /features/0/geometry/coordinates/0: a linear ring must be closed: its first and last positions must be equal",
+                "error at /features/1: Feature without \"properties\" member",
+                "warning at /features/1/geometry/coordinates/0: a position should not have more than three elements",
+                "error at /features/1/geometry/coordinates/0/0: longitude 200 is out of [-180, 180]",
+                "error at /features/1/geometry/coordinates: a LineString needs at least two positions",
+                "warning at /features/2/geometry/geometries/0: nested GeometryCollections should be avoided",
+                "error at /features/2/geometry/geometries/0/geometries/0/type: unknown type \"Circle\"",
+                "error at /features/3/type: expected \"Feature\", found \"Point\"",
+            ]
+        );
+        assert!(has_errors(&validate(&from_str(geojson).unwrap())));
+    }
+
+    #[test]
+    fn test_validate_compares_ring_ends_as_numbers() {
+        assert!(
+            diagnostics(r#"{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0.0,0.0]]]}"#)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_validate_reads_topojson_as_it_is() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, br#"{"type":"Topology","objects":{},"arcs":[]}"#)
+            .unwrap();
+
+        let diagnostics = handle_input_validation(file.path().to_str().unwrap()).unwrap();
+
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect::<Vec<_>>(),
+            vec!["error at /type: a TopoJSON Topology is not GeoJSON"]
+        );
+        assert!(has_errors(&diagnostics));
+    }
+
+    #[test]
+    fn test_validate_only_warnings_is_not_an_error() {
+        let diagnostics =
+            validate(&from_str(r#"{"type":"Point","coordinates":[0,0,0,0]}"#).unwrap());
+
+        assert_eq!(diagnostics.len(), 1);
+        assert!(!has_errors(&diagnostics));
+    }
+}